@@ -3,6 +3,12 @@ use std::path::PathBuf;
 use deno_core::Extension;
 use deno_core::JsRuntimeForSnapshot;
 
+use crate::runtime::CreateRuntimeOptions;
+use crate::runtime::JsRuntime;
+use crate::snapshot::serialize_snapshot;
+use crate::snapshot::SnapshotCompression;
+use crate::util::create_tokio_runtime;
+
 pub type WithRuntimeCb = dyn Fn(&mut JsRuntimeForSnapshot);
 
 pub struct CreateSnapshotOptions {
@@ -11,6 +17,9 @@ pub struct CreateSnapshotOptions {
   pub extensions: Vec<Extension>,
   pub with_runtime_cb: Option<Box<WithRuntimeCb>>,
   pub warmup_script: Option<&'static str>,
+  /// Compression to use for the snapshot file. This is recorded in the
+  /// snapshot so it doesn't need to match when deserializing.
+  pub compression: SnapshotCompression,
 }
 
 /// Creates a snapshot, returning the uncompressed bytes.
@@ -28,14 +37,7 @@ pub fn create_snapshot(options: CreateSnapshotOptions) -> Box<[u8]> {
   )
   .unwrap();
 
-  let mut output = snapshot_output.output.clone();
-  if !cfg!(debug_assertions) {
-    eprintln!("Compressing snapshot...");
-    let mut vec = Vec::with_capacity(output.len());
-    vec.extend((output.len() as u32).to_le_bytes());
-    vec.extend_from_slice(&zstd::bulk::compress(&output, 22).expect("snapshot compression failed"));
-    output = vec.into();
-  }
+  let output = serialize_snapshot(&snapshot_output.output, options.compression);
   std::fs::write(&options.snapshot_path, output).unwrap();
 
   for file in snapshot_output.files_loaded_during_snapshot {
@@ -44,3 +46,85 @@ pub fn create_snapshot(options: CreateSnapshotOptions) -> Box<[u8]> {
 
   snapshot_output.output
 }
+
+pub struct BuildPluginOptions {
+  pub cargo_manifest_dir: &'static str,
+  /// Directory to write the snapshot and the metadata files to,
+  /// which is usually `OUT_DIR`.
+  pub out_dir: PathBuf,
+  /// Path to the JS bundle that's executed as a script when creating
+  /// the snapshot and should set the `dprint` global.
+  pub bundle_path: PathBuf,
+  /// Extensions to include in the snapshot (`init_ops_and_esm`).
+  pub snapshot_extensions: Vec<Extension>,
+  /// The same extensions for the runtime created from the snapshot (`init_ops`).
+  pub runtime_extensions: Vec<Extension>,
+  /// Functions on the `dprint` global with no parameters to call after creating
+  /// the snapshot. The value each one resolves to is written as JSON to the
+  /// file name in the output directory.
+  pub metadata_fns: Vec<(&'static str, &'static str)>,
+  pub compression: SnapshotCompression,
+}
+
+/// Creates `STARTUP_SNAPSHOT.bin` and `SUPPORTED_EXTENSIONS.json`, which has
+/// the extensions from `dprint.getExtensions()`, in the output directory along
+/// with the files for the provided metadata functions.
+pub fn build_plugin(options: BuildPluginOptions) {
+  println!("cargo:rerun-if-changed={}", options.bundle_path.display());
+  let bundle_text = std::fs::read_to_string(&options.bundle_path).unwrap_or_else(|err| {
+    panic!(
+      "Error reading bundle {}: {}",
+      options.bundle_path.display(),
+      err
+    )
+  });
+
+  let snapshot = create_snapshot(CreateSnapshotOptions {
+    cargo_manifest_dir: options.cargo_manifest_dir,
+    snapshot_path: options.out_dir.join("STARTUP_SNAPSHOT.bin"),
+    extensions: options.snapshot_extensions,
+    with_runtime_cb: Some(Box::new({
+      let bundle_text = bundle_text.clone();
+      move |runtime| {
+        runtime
+          .execute_script("dprint:bundle.js", bundle_text.clone())
+          .unwrap();
+      }
+    })),
+    warmup_script: None,
+    compression: options.compression,
+  });
+  let snapshot = Box::leak(snapshot);
+
+  eprintln!("Creating runtime...");
+  let tokio_runtime = create_tokio_runtime();
+  JsRuntime::initialize_main_thread();
+  let mut runtime = JsRuntime::new(CreateRuntimeOptions {
+    extensions: options.runtime_extensions,
+    startup_snapshot: Some(snapshot),
+  });
+
+  eprintln!("Getting metadata...");
+  let metadata_fns = [("SUPPORTED_EXTENSIONS.json", "getExtensions")]
+    .into_iter()
+    .chain(options.metadata_fns);
+  tokio_runtime.block_on(async {
+    runtime
+      .execute_script("dprint:bundle.js", bundle_text)
+      .unwrap();
+    for (file_name, fn_name) in metadata_fns {
+      let fn_path = format!("dprint.{}", fn_name);
+      let func = runtime.get_fn(&fn_path).unwrap();
+      let value = runtime
+        .call_fn::<_, serde_json::Value>(&func, ())
+        .await
+        .unwrap_or_else(|err| panic!("Error calling {}: {:#}", fn_path, err));
+      std::fs::write(
+        options.out_dir.join(file_name),
+        serde_json::to_string(&value).unwrap(),
+      )
+      .unwrap();
+    }
+  });
+  eprintln!("Done");
+}
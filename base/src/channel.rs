@@ -1,6 +1,13 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
+use deno_core::anyhow::anyhow;
+use deno_core::anyhow::bail;
 use deno_core::anyhow::Error;
 use deno_core::parking_lot::Mutex;
 use dprint_core::async_runtime::async_trait;
@@ -8,32 +15,139 @@ use dprint_core::plugins::FormatRequest;
 use dprint_core::plugins::FormatResult;
 use tokio::sync::oneshot;
 
+use crate::priority_queue::Priority;
+use crate::priority_queue::PriorityQueue;
+use crate::runtime::HeapStatistics;
+use crate::trace::set_thread_tracer;
+use crate::trace::Span;
+use crate::trace::Tracer;
 use crate::util::create_tokio_runtime;
 use crate::util::system_available_memory;
 
+pub struct FormatWithCursorResult {
+  pub text: Vec<u8>,
+  /// Byte offset of the cursor in the formatted text.
+  pub cursor_offset: usize,
+}
+
 #[async_trait(?Send)]
-pub trait Formatter<TConfiguration> {
+pub trait Formatter<TConfiguration: 'static> {
   async fn format_text(
     &mut self,
     request: FormatRequest<TConfiguration>,
   ) -> Result<Option<Vec<u8>>, Error>;
+
+  /// Formats the text and maps the provided cursor byte offset to
+  /// its position in the formatted text.
+  async fn format_text_with_cursor(
+    &mut self,
+    _request: FormatRequest<TConfiguration>,
+    _cursor_offset: usize,
+  ) -> Result<Option<FormatWithCursorResult>, Error> {
+    bail!("Formatting with a cursor is not supported.")
+  }
+
+  /// Formats several files that use the same configuration at once, which
+  /// reduces the overhead per file. `on_result` should be called for each
+  /// file as soon as it's formatted so the result isn't held until the
+  /// whole batch is done.
+  async fn format_texts(
+    &mut self,
+    requests: Vec<FormatRequest<TConfiguration>>,
+    mut on_result: OnBatchResult,
+  ) {
+    for (index, request) in requests.into_iter().enumerate() {
+      let start = Instant::now();
+      let result = self.format_text(request).await;
+      on_result(BatchResult {
+        index,
+        result,
+        duration: start.elapsed(),
+      });
+    }
+  }
+
+  /// Heap statistics of the formatter's isolate, if it has one.
+  fn heap_statistics(&mut self) -> Option<HeapStatistics> {
+    None
+  }
 }
 
+/// The result of formatting one of the files passed to `Formatter::format_texts`.
+pub struct BatchResult {
+  /// The index of the file's request in the batch.
+  pub index: usize,
+  pub result: Result<Option<Vec<u8>>, Error>,
+  /// How long it took to format the file.
+  pub duration: Duration,
+}
+
+pub type OnBatchResult = Box<dyn FnMut(BatchResult)>;
+
 pub type CreateFormatterCb<TConfiguration> =
-  dyn Fn() -> Box<dyn Formatter<TConfiguration>> + Send + Sync;
+  dyn Fn() -> Result<Box<dyn Formatter<TConfiguration>>, Error> + Send + Sync;
 
 pub struct CreateChannelOptions<TConfiguration> {
-  /// The amount of memory that a single isolate might use. You can approximate
+  /// The amount of memory in bytes that a single isolate might use. You can approximate
   /// this value out by launching the plugin with DPRINT_MAX_THREADS=1 and seeing
-  /// how much memory is used when formatting some large files.
+  /// how much memory is used when formatting some large files or by using
+  /// the harness in the `memory` module.
   ///
   /// This provides some protection against using too much memory on the system,
   /// but is not perfect. It is better than nothing.
   pub avg_isolate_memory_usage: usize,
+  /// The approximate amount of memory used for each byte of a file while
+  /// it's being formatted (ex. for its AST). This is added to the isolate's
+  /// memory usage when deciding if there's enough memory for another one.
+  pub memory_per_file_byte: usize,
   pub create_formatter_cb: Arc<CreateFormatterCb<TConfiguration>>,
+  /// Records spans for the formatting done by the isolates when provided.
+  pub tracer: Option<Arc<Tracer>>,
 }
 
-type Request<TConfiguration> = (FormatRequest<TConfiguration>, oneshot::Sender<FormatResult>);
+pub type FormatWithCursorResponse = Result<Option<FormatWithCursorResult>, Error>;
+
+enum Response {
+  Format(oneshot::Sender<FormatResult>),
+  FormatWithCursor {
+    cursor_offset: usize,
+    sender: oneshot::Sender<FormatWithCursorResponse>,
+  },
+}
+
+impl Response {
+  fn send_error(self, err: Error) {
+    match self {
+      Response::Format(sender) => {
+        let _ = sender.send(Err(err));
+      }
+      Response::FormatWithCursor { sender, .. } => {
+        let _ = sender.send(Err(err));
+      }
+    }
+  }
+}
+
+/// A request along with when it was queued.
+type Request<TConfiguration> = (FormatRequest<TConfiguration>, Response, Instant);
+
+/// Files up to this size are formatted before larger ones since they're
+/// usually from an editor formatting the current file.
+const SMALL_FILE_BYTES: usize = 16 * 1024;
+/// The maximum number of small files to format in one call to the formatter.
+const MAX_BATCH_SIZE: usize = 20;
+/// How many high priority requests can be handled in a row while
+/// normal priority requests are waiting, which includes the ones
+/// formatted in a batch.
+const MAX_HIGH_PRIORITY_STREAK: usize = 8;
+
+fn get_priority<TConfiguration>(request: &FormatRequest<TConfiguration>) -> Priority {
+  if request.range.is_some() || request.file_bytes.len() <= SMALL_FILE_BYTES {
+    Priority::High
+  } else {
+    Priority::Normal
+  }
+}
 
 struct Stats {
   pending_runtimes: usize,
@@ -42,68 +156,129 @@ struct Stats {
 
 pub struct Channel<TConfiguration: Send + Sync + 'static> {
   stats: Arc<Mutex<Stats>>,
-  sender: async_channel::Sender<Request<TConfiguration>>,
-  receiver: async_channel::Receiver<Request<TConfiguration>>,
+  queue: Arc<PriorityQueue<Request<TConfiguration>>>,
   options: CreateChannelOptions<TConfiguration>,
+  next_isolate_id: AtomicUsize,
 }
 
 impl<TConfiguration: Send + Sync + 'static> Channel<TConfiguration> {
   pub fn new(options: CreateChannelOptions<TConfiguration>) -> Self {
-    let (sender, receiver) = async_channel::unbounded();
     Self {
       stats: Arc::new(Mutex::new(Stats {
         pending_runtimes: 0,
         total_runtimes: 0,
       })),
-      sender,
-      receiver,
+      queue: Arc::new(PriorityQueue::new(MAX_HIGH_PRIORITY_STREAK)),
       options,
+      next_isolate_id: AtomicUsize::new(1),
     }
   }
 
   pub async fn format(&self, request: FormatRequest<TConfiguration>) -> FormatResult {
     let (send, recv) = oneshot::channel::<FormatResult>();
+    self.send(request, Response::Format(send), recv).await
+  }
+
+  pub async fn format_with_cursor(
+    &self,
+    request: FormatRequest<TConfiguration>,
+    cursor_offset: usize,
+  ) -> FormatWithCursorResponse {
+    let (send, recv) = oneshot::channel::<FormatWithCursorResponse>();
+    let response = Response::FormatWithCursor {
+      cursor_offset,
+      sender: send,
+    };
+    self.send(request, response, recv).await
+  }
+
+  async fn send<T>(
+    &self,
+    request: FormatRequest<TConfiguration>,
+    response: Response,
+    recv: oneshot::Receiver<Result<T, Error>>,
+  ) -> Result<T, Error> {
     let mut should_inc_pending_runtimes = false;
     {
       let mut stats = self.stats.lock();
-      if stats.pending_runtimes == 0 && (stats.total_runtimes == 0 || self.has_memory_available()) {
+      if stats.pending_runtimes == 0
+        && (stats.total_runtimes == 0 || self.has_memory_available(request.file_bytes.len()))
+      {
         stats.total_runtimes += 1;
         stats.pending_runtimes += 1;
-        drop(stats);
         self.create_js_runtime();
       } else if stats.pending_runtimes > 0 {
         stats.pending_runtimes -= 1;
         should_inc_pending_runtimes = true;
       }
-    }
 
-    self.sender.send((request, send)).await?;
+      // queued while holding the lock so that an isolate that fails
+      // to start can't miss the request when failing the queue
+      let priority = get_priority(&request);
+      if self
+        .queue
+        .push((request, response, Instant::now()), priority)
+        .is_err()
+      {
+        bail!("The channel was closed.");
+      }
+    }
 
     let result = recv.await?;
     if should_inc_pending_runtimes {
-      self.stats.lock().pending_runtimes += 1;
+      let mut stats = self.stats.lock();
+      // an isolate may have failed to start while this was waiting
+      stats.pending_runtimes = (stats.pending_runtimes + 1).min(stats.total_runtimes);
     }
     result
   }
 
-  fn has_memory_available(&self) -> bool {
+  fn has_memory_available(&self, file_size: usize) -> bool {
     // Only allow creating another instance if the amount of available
     // memory on the system is greater than a comfortable amount
     let available_memory = system_available_memory();
+    let required_memory = self
+      .options
+      .avg_isolate_memory_usage
+      .saturating_add(file_size.saturating_mul(self.options.memory_per_file_byte));
     // I chose 2x because that would maybe prevent at least two plugins
     // from potentially creating an isolate at the same time and going over the
     // memory limit. It's definitely not perfect.
-    available_memory > (self.options.avg_isolate_memory_usage as f64 * 2.2) as u64
+    available_memory > (required_memory as f64 * 2.2) as u64
   }
 
   fn create_js_runtime(&self) {
     let stats = self.stats.clone();
-    let receiver = self.receiver.clone();
+    let queue = self.queue.clone();
     let create_formatter_cb = self.options.create_formatter_cb.clone();
+    let tracer = self.options.tracer.clone();
+    let isolate_id = self.next_isolate_id.fetch_add(1, Ordering::Relaxed);
     std::thread::spawn(move || {
+      if let Some(tracer) = tracer {
+        set_thread_tracer(tracer, isolate_id);
+      }
       let tokio_runtime = create_tokio_runtime();
       tokio_runtime.block_on(async move {
-        let mut formatter = (create_formatter_cb)();
+        let create_span = Span::new("create isolate");
+        let formatter = (create_formatter_cb)();
+        drop(create_span);
+        let mut formatter = match formatter {
+          Ok(formatter) => formatter,
+          Err(err) => {
+            let mut stats = stats.lock();
+            stats.total_runtimes = stats.total_runtimes.saturating_sub(1);
+            stats.pending_runtimes = stats.pending_runtimes.saturating_sub(1);
+            if stats.total_runtimes == 0 {
+              // there's no isolate left to handle the queued requests, so fail
+              // them and let the next request try creating one again
+              let message = format!("{:#}", err);
+              while let Some((_, response, _)) = queue.try_pop() {
+                response.send_error(anyhow!("{}", message));
+              }
+            }
+            return;
+          }
+        };
         loop {
           tokio::select! {
             // automatically shut down after a certain amount of time to save memory
@@ -117,16 +292,54 @@ impl<TConfiguration: Send + Sync + 'static> Channel<TConfiguration> {
                 return;
               }
             }
-            request = receiver.recv() => {
-              let (request, response) = match request {
-                Ok(result) => result,
-                Err(_) => {
-                  // receiver dropped, so exit
+            request = queue.pop() => {
+              let (request, response, queued_at) = match request {
+                Some(result) => result,
+                None => {
+                  // channel dropped, so exit
                   return;
                 }
               };
-              let result = formatter.format_text(request).await;
-              let _ = response.send(result);
+              drop(Span::with_start("queue wait", queued_at));
+              match response {
+                Response::Format(sender) if is_batchable(&request) => {
+                  let batch = queue.try_pop_matching(MAX_BATCH_SIZE - 1, |(other, response, _)| {
+                    matches!(response, Response::Format(_))
+                      && is_batchable(other)
+                      && other.config_id == request.config_id
+                      && Arc::ptr_eq(&other.config, &request.config)
+                  });
+                  if batch.is_empty() {
+                    format_single(&mut *formatter, request, sender).await;
+                  } else {
+                    let mut requests = Vec::with_capacity(batch.len() + 1);
+                    let mut senders = Vec::with_capacity(batch.len() + 1);
+                    requests.push(request);
+                    senders.push(sender);
+                    for (request, response, queued_at) in batch {
+                      drop(Span::with_start("queue wait", queued_at));
+                      if let Response::Format(sender) = response {
+                        requests.push(request);
+                        senders.push(sender);
+                      }
+                    }
+                    format_batch(&mut *formatter, requests, senders).await;
+                  }
+                }
+                Response::Format(sender) => {
+                  format_single(&mut *formatter, request, sender).await;
+                }
+                Response::FormatWithCursor { cursor_offset, sender } => {
+                  let mut span = Span::file(&request.file_path);
+                  span.arg("bytesIn", request.file_bytes.len());
+                  let result = formatter.format_text_with_cursor(request, cursor_offset).await;
+                  if let Ok(Some(result)) = &result {
+                    span.arg("bytesOut", result.text.len());
+                  }
+                  drop(span);
+                  let _ = sender.send(result);
+                }
+              }
             }
           }
         }
@@ -134,3 +347,87 @@ impl<TConfiguration: Send + Sync + 'static> Channel<TConfiguration> {
     });
   }
 }
+
+/// Gets if the request may be formatted in a batch with other requests.
+fn is_batchable<TConfiguration>(request: &FormatRequest<TConfiguration>) -> bool {
+  request.range.is_none() && request.file_bytes.len() <= SMALL_FILE_BYTES
+}
+
+async fn format_single<TConfiguration: 'static>(
+  formatter: &mut dyn Formatter<TConfiguration>,
+  request: FormatRequest<TConfiguration>,
+  sender: oneshot::Sender<FormatResult>,
+) {
+  let mut span = Span::file(&request.file_path);
+  span.arg("bytesIn", request.file_bytes.len());
+  let result = formatter.format_text(request).await;
+  if let Ok(Some(text)) = &result {
+    span.arg("bytesOut", text.len());
+  }
+  drop(span);
+  let _ = sender.send(result);
+}
+
+async fn format_batch<TConfiguration: 'static>(
+  formatter: &mut dyn Formatter<TConfiguration>,
+  requests: Vec<FormatRequest<TConfiguration>>,
+  senders: Vec<oneshot::Sender<FormatResult>>,
+) {
+  let mut span = Span::new("format batch");
+  span.arg(
+    "filePaths",
+    requests
+      .iter()
+      .map(|request| request.file_path.to_string_lossy().to_string())
+      .collect::<Vec<_>>(),
+  );
+  span.arg(
+    "bytesIn",
+    requests
+      .iter()
+      .map(|request| request.file_bytes.len())
+      .sum::<usize>(),
+  );
+  let files = requests
+    .iter()
+    .map(|request| (request.file_path.clone(), request.file_bytes.len()))
+    .collect::<Vec<_>>();
+  let senders = Rc::new(RefCell::new(
+    senders.into_iter().map(Some).collect::<Vec<_>>(),
+  ));
+  let on_result = {
+    let senders = senders.clone();
+    Box::new(move |batch_result: BatchResult| {
+      let Some(sender) = senders
+        .borrow_mut()
+        .get_mut(batch_result.index)
+        .and_then(Option::take)
+      else {
+        return;
+      };
+      let (file_path, bytes_in) = &files[batch_result.index];
+      let start = Instant::now()
+        .checked_sub(batch_result.duration)
+        .unwrap_or_else(Instant::now);
+      let mut file_span = Span::file_with_start(file_path, start);
+      file_span.arg("bytesIn", *bytes_in);
+      if let Ok(Some(text)) = &batch_result.result {
+        file_span.arg("bytesOut", text.len());
+      }
+      drop(file_span);
+      let _ = sender.send(batch_result.result);
+    })
+  };
+  formatter.format_texts(requests, on_result).await;
+  drop(span);
+  for sender in senders.borrow_mut().drain(..).flatten() {
+    let _ = sender.send(Err(anyhow!("Missing result from the batch.")));
+  }
+}
+
+impl<TConfiguration: Send + Sync + 'static> Drop for Channel<TConfiguration> {
+  fn drop(&mut self) {
+    // lets the isolates exit once they've handled the queued requests
+    self.queue.close();
+  }
+}
@@ -1,6 +1,10 @@
 #[cfg(feature = "build")]
 pub mod build;
 pub mod channel;
+pub mod memory;
+pub mod plugin;
+pub mod priority_queue;
 pub mod runtime;
 pub mod snapshot;
+pub mod trace;
 pub mod util;
@@ -0,0 +1,166 @@
+//! Harness for measuring how much memory a formatter's isolate uses, which
+//! is what `CreateChannelOptions::avg_isolate_memory_usage` should be based on.
+
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+use deno_core::anyhow::Error;
+use dprint_core::plugins::FormatRequest;
+use sysinfo::ProcessRefreshKind;
+use sysinfo::ProcessesToUpdate;
+use sysinfo::System;
+
+use crate::channel::CreateFormatterCb;
+use crate::runtime::HeapStatistics;
+
+pub struct MemoryReport {
+  pub name: String,
+  pub file_count: usize,
+  pub total_file_bytes: usize,
+  pub elapsed: Duration,
+  /// Heap statistics of the isolate after formatting every file.
+  pub heap: Option<HeapStatistics>,
+  /// Resident set size of the process before creating the isolate.
+  pub rss_before: u64,
+  /// Resident set size of the process after formatting every file.
+  pub rss_after: u64,
+  /// Peak resident set size while formatting. Only available on Linux.
+  pub peak_rss: Option<u64>,
+}
+
+impl MemoryReport {
+  /// Memory attributed to the isolate, which is what
+  /// `avg_isolate_memory_usage` should approximate.
+  pub fn isolate_memory(&self) -> u64 {
+    let rss_delta = self
+      .peak_rss
+      .unwrap_or(self.rss_after)
+      .saturating_sub(self.rss_before);
+    let heap = self
+      .heap
+      .map(|heap| (heap.total_heap_size + heap.external_memory + heap.malloced_memory) as u64)
+      .unwrap_or(0);
+    rss_delta.max(heap)
+  }
+}
+
+impl fmt::Display for MemoryReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(
+      f,
+      "{}: {} files ({}) in {:.2?}",
+      self.name,
+      self.file_count,
+      format_bytes(self.total_file_bytes as u64),
+      self.elapsed
+    )?;
+    if let Some(heap) = &self.heap {
+      writeln!(
+        f,
+        "  heap: {} used / {} total (limit {}), external {}, malloced {} (peak {})",
+        format_bytes(heap.used_heap_size as u64),
+        format_bytes(heap.total_heap_size as u64),
+        format_bytes(heap.heap_size_limit as u64),
+        format_bytes(heap.external_memory as u64),
+        format_bytes(heap.malloced_memory as u64),
+        format_bytes(heap.peak_malloced_memory as u64),
+      )?;
+    }
+    write!(
+      f,
+      "  rss: {} before, {} after",
+      format_bytes(self.rss_before),
+      format_bytes(self.rss_after)
+    )?;
+    if let Some(peak_rss) = self.peak_rss {
+      write!(f, ", {} peak", format_bytes(peak_rss))?;
+    }
+    write!(
+      f,
+      "\n  isolate memory: {}",
+      format_bytes(self.isolate_memory())
+    )
+  }
+}
+
+/// Creates a formatter, formats the requests with it, then reports
+/// the memory used by its isolate.
+///
+/// Run each workload on its own so that memory from previous isolates
+/// doesn't affect the measurement. For example, with DPRINT_MAX_THREADS=1
+/// and one workload per process.
+pub async fn measure_memory<TConfiguration: 'static>(
+  name: impl Into<String>,
+  create_formatter_cb: &CreateFormatterCb<TConfiguration>,
+  requests: Vec<FormatRequest<TConfiguration>>,
+) -> Result<MemoryReport, Error> {
+  reset_peak_rss();
+  let rss_before = current_rss();
+  let start = Instant::now();
+  let file_count = requests.len();
+  let total_file_bytes = requests
+    .iter()
+    .map(|request| request.file_bytes.len())
+    .sum();
+
+  let mut formatter = (create_formatter_cb)()?;
+  for request in requests {
+    formatter.format_text(request).await?;
+  }
+
+  Ok(MemoryReport {
+    name: name.into(),
+    file_count,
+    total_file_bytes,
+    elapsed: start.elapsed(),
+    heap: formatter.heap_statistics(),
+    rss_before,
+    rss_after: current_rss(),
+    peak_rss: peak_rss(),
+  })
+}
+
+fn current_rss() -> u64 {
+  let Ok(pid) = sysinfo::get_current_pid() else {
+    return 0;
+  };
+  let mut sys = System::new();
+  sys.refresh_processes_specifics(
+    ProcessesToUpdate::Some(&[pid]),
+    true,
+    ProcessRefreshKind::nothing().with_memory(),
+  );
+  sys
+    .process(pid)
+    .map(|process| process.memory())
+    .unwrap_or(0)
+}
+
+/// Resets the peak resident set size reported by `peak_rss`.
+fn reset_peak_rss() {
+  if cfg!(target_os = "linux") {
+    // see "clear_refs" in `man proc`
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+  }
+}
+
+fn peak_rss() -> Option<u64> {
+  if !cfg!(target_os = "linux") {
+    return None;
+  }
+  let status = std::fs::read_to_string("/proc/self/status").ok()?;
+  let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+  let kb = line
+    .trim_start_matches("VmHWM:")
+    .trim()
+    .trim_end_matches("kB")
+    .trim()
+    .parse::<u64>()
+    .ok()?;
+  Some(kb * 1024)
+}
+
+fn format_bytes(bytes: u64) -> String {
+  format!("{:.1}MB", bytes as f64 / 1024.0 / 1024.0)
+}
@@ -0,0 +1,358 @@
+//! Higher-level pieces for creating a formatting plugin from a JS bundle.
+//!
+//! Use `build::build_plugin` in the build script to create the snapshot, then
+//! load it with a `StartupSnapshot` and create the `AsyncPluginHandler` with
+//! `JsPluginHandlerBuilder::snapshot`.
+//!
+//! The bundle is expected to set a `dprint` global with the following functions
+//! that follow the standard calling convention:
+//!
+//! - `getExtensions(): Promise<string[]>` - Called at build time.
+//! - `formatText({ filePath, fileText, config }): Promise<string | undefined>` -
+//!   Returns `undefined` when the text doesn't change.
+//! - `formatTextWithCursor({ filePath, fileText, config, cursorOffset })` - Optional.
+//!   Resolves to `{ formatted, cursorOffset }` or `undefined` where the cursor
+//!   offset is in UTF-16 code units.
+
+use std::sync::Arc;
+use std::sync::OnceLock;
+
+use deno_core::anyhow::anyhow;
+use deno_core::anyhow::Error;
+use deno_core::Extension;
+use dprint_core::async_runtime::async_trait;
+use dprint_core::async_runtime::LocalBoxFuture;
+use dprint_core::configuration::ConfigKeyMap;
+use dprint_core::configuration::GlobalConfiguration;
+use dprint_core::plugins::AsyncPluginHandler;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::FormatResult;
+use dprint_core::plugins::HostFormatRequest;
+use dprint_core::plugins::PluginInfo;
+use dprint_core::plugins::PluginResolveConfigurationResult;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::channel::Channel;
+use crate::channel::CreateChannelOptions;
+use crate::channel::CreateFormatterCb;
+use crate::channel::FormatWithCursorResponse;
+use crate::channel::FormatWithCursorResult;
+use crate::channel::Formatter;
+use crate::runtime::CreateRuntimeOptions;
+use crate::runtime::HeapStatistics;
+use crate::runtime::JsRuntime;
+use crate::snapshot::deserialize_snapshot;
+use crate::trace::Tracer;
+use crate::util::set_v8_max_memory;
+use crate::util::utf16_to_utf8_offset;
+use crate::util::utf8_to_utf16_offset;
+
+/// A snapshot created by `build::build_plugin` that's deserialized
+/// the first time it's used.
+///
+/// ```ignore
+/// static STARTUP_SNAPSHOT: StartupSnapshot =
+///   StartupSnapshot::new(include_bytes!(concat!(env!("OUT_DIR"), "/STARTUP_SNAPSHOT.bin")));
+/// ```
+pub struct StartupSnapshot {
+  serialized: &'static [u8],
+  v8_max_memory: Option<usize>,
+  deserialized: OnceLock<Result<Box<[u8]>, String>>,
+}
+
+impl StartupSnapshot {
+  pub const fn new(serialized: &'static [u8]) -> Self {
+    Self {
+      serialized,
+      v8_max_memory: None,
+      deserialized: OnceLock::new(),
+    }
+  }
+
+  /// Sets the v8 max old space size in megabytes when the snapshot is
+  /// first loaded, which is before any isolates are created.
+  pub const fn with_v8_max_memory(mut self, megabytes: usize) -> Self {
+    self.v8_max_memory = Some(megabytes);
+    self
+  }
+
+  pub fn get(&'static self) -> Result<&'static [u8], Error> {
+    self
+      .deserialized
+      .get_or_init(
+        #[cold]
+        #[inline(never)]
+        || {
+          if let Some(max_memory) = self.v8_max_memory {
+            set_v8_max_memory(max_memory);
+          }
+          deserialize_snapshot(self.serialized).map_err(|err| format!("{:#}", err))
+        },
+      )
+      .as_deref()
+      .map_err(|err| anyhow!("Failed loading the snapshot: {}", err))
+  }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatTextArgs<'a, TConfiguration> {
+  file_path: &'a str,
+  file_text: &'a str,
+  config: &'a TConfiguration,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  cursor_offset: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatWithCursorOutput {
+  formatted: String,
+  cursor_offset: usize,
+}
+
+/// A formatter that calls the functions of the standard calling
+/// convention with the serialized configuration.
+pub struct JsFormatter {
+  runtime: JsRuntime,
+}
+
+impl JsFormatter {
+  /// Creates the formatter from the snapshot. The extensions should be
+  /// created with `init_ops` as they're already in the snapshot.
+  pub fn new(snapshot: &'static [u8], extensions: Vec<Extension>) -> Self {
+    Self::from_runtime(JsRuntime::new(CreateRuntimeOptions {
+      extensions,
+      startup_snapshot: Some(snapshot),
+    }))
+  }
+
+  /// Uses a runtime where the `dprint` global is already set.
+  pub fn from_runtime(runtime: JsRuntime) -> Self {
+    Self { runtime }
+  }
+}
+
+#[async_trait(?Send)]
+impl<TConfiguration: Serialize + 'static> Formatter<TConfiguration> for JsFormatter {
+  async fn format_text(
+    &mut self,
+    request: FormatRequest<TConfiguration>,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    let file_path = request.file_path.to_string_lossy();
+    let args = FormatTextArgs {
+      file_path: &file_path,
+      file_text: std::str::from_utf8(&request.file_bytes)?,
+      config: request.config.as_ref(),
+      cursor_offset: None,
+    };
+    let format_text = self.runtime.get_fn("dprint.formatText")?;
+    let formatted = self
+      .runtime
+      .call_fn::<_, Option<String>>(&format_text, (args,))
+      .await?;
+    Ok(formatted.map(|text| text.into_bytes()))
+  }
+
+  async fn format_text_with_cursor(
+    &mut self,
+    request: FormatRequest<TConfiguration>,
+    cursor_offset: usize,
+  ) -> Result<Option<FormatWithCursorResult>, Error> {
+    let file_path = request.file_path.to_string_lossy();
+    let file_text = std::str::from_utf8(&request.file_bytes)?;
+    let args = FormatTextArgs {
+      file_path: &file_path,
+      file_text,
+      config: request.config.as_ref(),
+      cursor_offset: Some(utf8_to_utf16_offset(file_text, cursor_offset)),
+    };
+    let format_text_with_cursor = self.runtime.get_fn("dprint.formatTextWithCursor")?;
+    let output = self
+      .runtime
+      .call_fn::<_, Option<FormatWithCursorOutput>>(&format_text_with_cursor, (args,))
+      .await?;
+    Ok(output.map(|output| FormatWithCursorResult {
+      cursor_offset: utf16_to_utf8_offset(&output.formatted, output.cursor_offset),
+      text: output.formatted.into_bytes(),
+    }))
+  }
+
+  fn heap_statistics(&mut self) -> Option<HeapStatistics> {
+    Some(self.runtime.heap_statistics())
+  }
+}
+
+pub type ResolveConfigCb<TConfiguration> = dyn Fn(ConfigKeyMap, GlobalConfiguration) -> PluginResolveConfigurationResult<TConfiguration>
+  + Send
+  + Sync;
+
+/// Builds a `JsPluginHandler`.
+pub struct JsPluginHandlerBuilder<TConfiguration: Serialize + Clone + Send + Sync + 'static> {
+  plugin_info: PluginInfo,
+  license_text: String,
+  resolve_config: Arc<ResolveConfigCb<TConfiguration>>,
+  create_formatter_cb: Option<Arc<CreateFormatterCb<TConfiguration>>>,
+  avg_isolate_memory_usage: usize,
+  memory_per_file_byte: usize,
+  tracer: Option<Arc<Tracer>>,
+}
+
+impl<TConfiguration: Serialize + Clone + Send + Sync + 'static>
+  JsPluginHandlerBuilder<TConfiguration>
+{
+  pub fn new(
+    plugin_info: PluginInfo,
+    resolve_config: impl Fn(ConfigKeyMap, GlobalConfiguration) -> PluginResolveConfigurationResult<TConfiguration>
+      + Send
+      + Sync
+      + 'static,
+  ) -> Self {
+    Self {
+      plugin_info,
+      license_text: String::new(),
+      resolve_config: Arc::new(resolve_config),
+      create_formatter_cb: None,
+      avg_isolate_memory_usage: 600 * 1024 * 1024,
+      memory_per_file_byte: 50,
+      tracer: None,
+    }
+  }
+
+  pub fn license_text(mut self, license_text: impl Into<String>) -> Self {
+    self.license_text = license_text.into();
+    self
+  }
+
+  /// Formats with a `JsFormatter` created from the snapshot. The extensions
+  /// should be created with `init_ops` as they're already in the snapshot.
+  pub fn snapshot(
+    self,
+    snapshot: &'static StartupSnapshot,
+    create_extensions: impl Fn() -> Vec<Extension> + Send + Sync + 'static,
+  ) -> Self {
+    self.formatter(move || {
+      Ok(Box::new(JsFormatter::new(
+        snapshot.get()?,
+        create_extensions(),
+      )))
+    })
+  }
+
+  /// Formats with a custom formatter instead of a `JsFormatter`, which is
+  /// for plugins that need more than the standard calling convention (ex.
+  /// caching the configuration per extension in the isolate).
+  pub fn formatter(
+    mut self,
+    create_formatter_cb: impl Fn() -> Result<Box<dyn Formatter<TConfiguration>>, Error>
+      + Send
+      + Sync
+      + 'static,
+  ) -> Self {
+    self.create_formatter_cb = Some(Arc::new(create_formatter_cb));
+    self
+  }
+
+  /// See `CreateChannelOptions::avg_isolate_memory_usage`.
+  pub fn avg_isolate_memory_usage(mut self, bytes: usize) -> Self {
+    self.avg_isolate_memory_usage = bytes;
+    self
+  }
+
+  /// See `CreateChannelOptions::memory_per_file_byte`.
+  pub fn memory_per_file_byte(mut self, bytes: usize) -> Self {
+    self.memory_per_file_byte = bytes;
+    self
+  }
+
+  pub fn tracer(mut self, tracer: Option<Arc<Tracer>>) -> Self {
+    self.tracer = tracer;
+    self
+  }
+
+  pub fn build(self) -> JsPluginHandler<TConfiguration> {
+    let create_formatter_cb = self
+      .create_formatter_cb
+      .expect("Provide a snapshot or formatter before building the plugin handler.");
+    JsPluginHandler {
+      plugin_info: self.plugin_info,
+      license_text: self.license_text,
+      resolve_config: self.resolve_config,
+      channel: Arc::new(Channel::new(CreateChannelOptions {
+        avg_isolate_memory_usage: self.avg_isolate_memory_usage,
+        memory_per_file_byte: self.memory_per_file_byte,
+        create_formatter_cb,
+        tracer: self.tracer,
+      })),
+    }
+  }
+}
+
+/// A plugin handler that formats files on isolates from a `Channel`.
+pub struct JsPluginHandler<TConfiguration: Serialize + Clone + Send + Sync + 'static> {
+  plugin_info: PluginInfo,
+  license_text: String,
+  resolve_config: Arc<ResolveConfigCb<TConfiguration>>,
+  channel: Arc<Channel<TConfiguration>>,
+}
+
+impl<TConfiguration: Serialize + Clone + Send + Sync + 'static> JsPluginHandler<TConfiguration> {
+  pub fn channel(&self) -> &Arc<Channel<TConfiguration>> {
+    &self.channel
+  }
+
+  /// Formats the file and maps the cursor byte offset to its position in the
+  /// formatted text so editors can preserve the cursor on format.
+  ///
+  /// Returns `None` when the text doesn't change.
+  pub async fn format_with_cursor(
+    &self,
+    request: FormatRequest<TConfiguration>,
+    cursor_offset: usize,
+  ) -> FormatWithCursorResponse {
+    if request.range.is_some() {
+      // no support for range formatting
+      return Ok(None);
+    }
+    self
+      .channel
+      .format_with_cursor(request, cursor_offset)
+      .await
+  }
+}
+
+#[async_trait(?Send)]
+impl<TConfiguration: Serialize + Clone + Send + Sync + 'static> AsyncPluginHandler
+  for JsPluginHandler<TConfiguration>
+{
+  type Configuration = TConfiguration;
+
+  fn plugin_info(&self) -> PluginInfo {
+    self.plugin_info.clone()
+  }
+
+  fn license_text(&self) -> String {
+    self.license_text.clone()
+  }
+
+  async fn resolve_config(
+    &self,
+    config: ConfigKeyMap,
+    global_config: GlobalConfiguration,
+  ) -> PluginResolveConfigurationResult<Self::Configuration> {
+    (self.resolve_config)(config, global_config)
+  }
+
+  async fn format(
+    &self,
+    request: FormatRequest<Self::Configuration>,
+    _format_with_host: impl FnMut(HostFormatRequest) -> LocalBoxFuture<'static, FormatResult> + 'static,
+  ) -> FormatResult {
+    if request.range.is_some() {
+      // no support for range formatting
+      return Ok(None);
+    }
+    self.channel.format(request).await
+  }
+}
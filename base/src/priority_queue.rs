@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+
+use deno_core::parking_lot::Mutex;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+  /// Work that someone is likely waiting on, such as formatting
+  /// a single file on save in an editor.
+  High,
+  Normal,
+}
+
+struct QueueState<T> {
+  high: VecDeque<T>,
+  normal: VecDeque<T>,
+  /// Number of high priority items popped in a row while
+  /// normal priority items were waiting.
+  high_streak: usize,
+  closed: bool,
+}
+
+/// A multi-consumer queue where high priority items jump ahead of normal
+/// priority ones.
+///
+/// To prevent normal priority items from being starved, one is popped after
+/// every `max_high_streak` high priority items while any are waiting.
+pub struct PriorityQueue<T> {
+  state: Mutex<QueueState<T>>,
+  notify: Notify,
+  max_high_streak: usize,
+}
+
+impl<T> PriorityQueue<T> {
+  pub fn new(max_high_streak: usize) -> Self {
+    Self {
+      state: Mutex::new(QueueState {
+        high: Default::default(),
+        normal: Default::default(),
+        high_streak: 0,
+        closed: false,
+      }),
+      notify: Notify::new(),
+      max_high_streak: max_high_streak.max(1),
+    }
+  }
+
+  /// Adds an item to the queue. Returns the item back if the queue is closed.
+  pub fn push(&self, item: T, priority: Priority) -> Result<(), T> {
+    {
+      let mut state = self.state.lock();
+      if state.closed {
+        return Err(item);
+      }
+      match priority {
+        Priority::High => state.high.push_back(item),
+        Priority::Normal => state.normal.push_back(item),
+      }
+    }
+    self.notify.notify_one();
+    Ok(())
+  }
+
+  /// Removes the next item from the queue if there is one.
+  pub fn try_pop(&self) -> Option<T> {
+    let mut state = self.state.lock();
+    if state.normal.is_empty() {
+      state.high_streak = 0;
+      return state.high.pop_front();
+    }
+    if !state.high.is_empty() && state.high_streak < self.max_high_streak {
+      state.high_streak += 1;
+      return state.high.pop_front();
+    }
+    state.high_streak = 0;
+    state.normal.pop_front()
+  }
+
+  /// Removes up to `max` items that match the predicate in the
+  /// order they were pushed, starting with the high priority ones.
+  ///
+  /// High priority items taken while normal priority items are waiting
+  /// count toward the streak, so this stops taking them once the next
+  /// `try_pop` would return a normal priority item.
+  pub fn try_pop_matching(&self, max: usize, predicate: impl Fn(&T) -> bool) -> Vec<T> {
+    let mut state = self.state.lock();
+    let state = &mut *state;
+    let mut items = Vec::new();
+    let mut index = 0;
+    while index < state.high.len() && items.len() < max {
+      let normal_waiting = !state.normal.is_empty();
+      if normal_waiting && state.high_streak >= self.max_high_streak {
+        break;
+      }
+      if predicate(&state.high[index]) {
+        items.extend(state.high.remove(index));
+        if normal_waiting {
+          state.high_streak += 1;
+        }
+      } else {
+        index += 1;
+      }
+    }
+    let mut index = 0;
+    while index < state.normal.len() && items.len() < max {
+      if predicate(&state.normal[index]) {
+        items.extend(state.normal.remove(index));
+        state.high_streak = 0;
+      } else {
+        index += 1;
+      }
+    }
+    items
+  }
+
+  /// Waits for the next item. Returns `None` once the queue
+  /// is closed and all its items have been popped.
+  pub async fn pop(&self) -> Option<T> {
+    loop {
+      let notified = self.notify.notified();
+      tokio::pin!(notified);
+      // register for notifications before checking the state
+      // so a push in between isn't missed
+      notified.as_mut().enable();
+      if let Some(item) = self.try_pop() {
+        return Some(item);
+      }
+      if self.state.lock().closed {
+        return None;
+      }
+      notified.await;
+    }
+  }
+
+  /// Closes the queue, which stops it from accepting new items. Items
+  /// already in the queue can still be popped.
+  pub fn close(&self) {
+    self.state.lock().closed = true;
+    self.notify.notify_waiters();
+  }
+
+  pub fn len(&self) -> usize {
+    let state = self.state.lock();
+    state.high.len() + state.normal.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
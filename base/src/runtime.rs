@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+use std::fmt;
+
 use deno_core::anyhow::anyhow;
 use deno_core::anyhow::Error;
 use deno_core::anyhow::Result;
+use deno_core::error::JsError;
 use deno_core::serde_v8;
 use deno_core::v8;
 use deno_core::v8::Platform;
@@ -8,7 +12,10 @@ use deno_core::v8::SharedRef;
 use deno_core::Extension;
 use deno_core::PollEventLoopOptions;
 use deno_core::RuntimeOptions;
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::trace::Span;
 
 fn get_platform() -> SharedRef<Platform> {
   static PLATFORM: std::sync::OnceLock<SharedRef<Platform>> = std::sync::OnceLock::new();
@@ -25,6 +32,51 @@ pub struct CreateRuntimeOptions {
 
 pub struct JsRuntime {
   inner: deno_core::JsRuntime,
+  /// Functions looked up with `get_fn`.
+  fns: HashMap<String, v8::Global<v8::Function>>,
+}
+
+/// An exception thrown by a function called with `JsRuntime::call_fn`.
+#[derive(Debug, Clone)]
+pub struct JsCallError {
+  /// The name of the error class (ex. `SyntaxError`).
+  pub name: Option<String>,
+  pub message: String,
+  pub stack: Option<String>,
+}
+
+impl From<JsError> for JsCallError {
+  fn from(err: JsError) -> Self {
+    JsCallError {
+      name: err.name,
+      message: err.message.unwrap_or(err.exception_message),
+      stack: err.stack,
+    }
+  }
+}
+
+impl fmt::Display for JsCallError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // the stack includes the name and message
+    match (&self.stack, &self.name) {
+      (Some(stack), _) => write!(f, "{}", stack),
+      (None, Some(name)) => write!(f, "{}: {}", name, self.message),
+      (None, None) => write!(f, "{}", self.message),
+    }
+  }
+}
+
+impl std::error::Error for JsCallError {}
+
+/// V8 heap statistics of an isolate in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStatistics {
+  pub total_heap_size: usize,
+  pub used_heap_size: usize,
+  pub heap_size_limit: usize,
+  pub external_memory: usize,
+  pub malloced_memory: usize,
+  pub peak_malloced_memory: usize,
 }
 
 impl JsRuntime {
@@ -36,6 +88,7 @@ impl JsRuntime {
         extensions: options.extensions,
         ..Default::default()
       }),
+      fns: Default::default(),
     }
   }
 
@@ -44,51 +97,220 @@ impl JsRuntime {
     deno_core::JsRuntime::init_platform(Some(get_platform()), false)
   }
 
-  pub async fn execute_format_script(&mut self, code: String) -> Result<Option<String>, Error> {
-    let global = self.inner.execute_script("format.js", code)?;
-    let resolve = self.inner.resolve(global);
-    let global = self
-      .inner
-      .with_event_loop_promise(resolve, PollEventLoopOptions::default())
-      .await?;
-    let scope = &mut self.inner.handle_scope();
-    let local = v8::Local::new(scope, global);
-    if local.is_undefined() {
-      Ok(None)
-    } else {
-      let deserialized_value = serde_v8::from_v8::<String>(scope, local);
-      match deserialized_value {
-        Ok(value) => Ok(Some(value)),
-        Err(err) => Err(anyhow!("Cannot deserialize serde_v8 value: {:#}", err)),
+  pub fn heap_statistics(&mut self) -> HeapStatistics {
+    let mut stats = v8::HeapStatistics::default();
+    self.inner.v8_isolate().get_heap_statistics(&mut stats);
+    HeapStatistics {
+      total_heap_size: stats.total_heap_size(),
+      used_heap_size: stats.used_heap_size(),
+      heap_size_limit: stats.heap_size_limit(),
+      external_memory: stats.external_memory(),
+      malloced_memory: stats.malloced_memory(),
+      peak_malloced_memory: stats.peak_malloced_memory(),
+    }
+  }
+
+  /// Gets the function at the provided path from the global object
+  /// (ex. `dprint.formatText`).
+  ///
+  /// The function is cached, so reassigning the property in JS
+  /// afterwards has no effect on the returned function.
+  pub fn get_fn(&mut self, path: &str) -> Result<v8::Global<v8::Function>, Error> {
+    if let Some(func) = self.fns.get(path) {
+      return Ok(func.clone());
+    }
+    let func = {
+      let scope = &mut self.inner.handle_scope();
+      let context = scope.get_current_context();
+      let mut value: v8::Local<v8::Value> = context.global(scope).into();
+      for key in path.split('.') {
+        let object = v8::Local::<v8::Object>::try_from(value)
+          .map_err(|_| anyhow!("Cannot get {} from a value that's not an object.", path))?;
+        let key = v8::String::new(scope, key).ok_or_else(|| anyhow!("Invalid key: {}", key))?;
+        value = object
+          .get(scope, key.into())
+          .ok_or_else(|| anyhow!("Failed getting {}.", path))?;
       }
+      let func = v8::Local::<v8::Function>::try_from(value)
+        .map_err(|_| anyhow!("Expected {} to be a function.", path))?;
+      v8::Global::new(scope, func)
+    };
+    self.fns.insert(path.to_string(), func.clone());
+    Ok(func)
+  }
+
+  /// Calls the function, waits for the promise it returns to resolve,
+  /// and deserializes the value.
+  ///
+  /// The arguments must serialize to an array of the arguments (ex. a
+  /// tuple like `(options,)`) or to nothing for `()`. Exceptions thrown
+  /// by the function are returned as a `JsCallError`.
+  pub async fn call_fn<A: Serialize, R: DeserializeOwned>(
+    &mut self,
+    func: &v8::Global<v8::Function>,
+    args: A,
+  ) -> Result<R, Error> {
+    let args = self.serialize_args(args)?;
+    self.call_with_args(func, args).await
+  }
+
+  /// Calls the function like `call_fn` with a callback as an additional last
+  /// argument. Each value the function passes to the callback is deserialized
+  /// and provided to `on_value` right away rather than when the call ends.
+  pub async fn call_fn_with_callback<A: Serialize, R: DeserializeOwned, V: DeserializeOwned>(
+    &mut self,
+    func: &v8::Global<v8::Function>,
+    args: A,
+    mut on_value: impl FnMut(V) + 'static,
+  ) -> Result<R, Error> {
+    let mut args = self.serialize_args(args)?;
+    {
+      let scope = &mut self.inner.handle_scope();
+      let callback = v8::Function::new(scope, call_value_callback)
+        .ok_or_else(|| anyhow!("Failed creating the callback function."))?;
+      let callback: v8::Local<v8::Value> = callback.into();
+      args.push(v8::Global::new(scope, callback));
     }
+    self
+      .inner
+      .v8_isolate()
+      .set_slot(ValueCallback(Box::new(move |scope, value| {
+        let value = serde_v8::from_v8::<V>(scope, value)
+          .map_err(|err| anyhow!("Cannot deserialize serde_v8 value: {:#}", err))?;
+        on_value(value);
+        Ok(())
+      })));
+    let result = self.call_with_args(func, args).await;
+    self.inner.v8_isolate().remove_slot::<ValueCallback>();
+    result
   }
 
-  pub fn execute_script(&mut self, script_name: &'static str, code: String) -> Result<(), Error> {
-    self.inner.execute_script(script_name, code).map(|_| ())
+  fn serialize_args<A: Serialize>(&mut self, args: A) -> Result<Vec<v8::Global<v8::Value>>, Error> {
+    let scope = &mut self.inner.handle_scope();
+    let value = serde_v8::to_v8(scope, args)
+      .map_err(|err| anyhow!("Cannot serialize serde_v8 value: {:#}", err))?;
+    let object_prototype = v8::Object::new(scope)
+      .get_prototype(scope)
+      .ok_or_else(|| anyhow!("Failed getting Object.prototype."))?;
+    set_object_prototypes(scope, value, object_prototype);
+    to_fn_args(scope, value)
   }
 
-  pub async fn execute_async_fn<'de, T>(
+  async fn call_with_args<R: DeserializeOwned>(
     &mut self,
-    script_name: &'static str,
-    fn_name: String,
-  ) -> Result<T>
-  where
-    T: Deserialize<'de>,
-  {
-    let inner = &mut self.inner;
-    let fn_value = inner.execute_script(script_name, fn_name)?;
-    let fn_value = inner.resolve(fn_value).await?;
-    let mut scope = inner.handle_scope();
-    let fn_func: v8::Local<v8::Function> = v8::Local::new(&mut scope, fn_value).try_into()?;
-    let fn_func = v8::Global::new(&mut scope, fn_func);
-    drop(scope);
-    let call = inner.call(&fn_func);
-    let result = inner
+    func: &v8::Global<v8::Function>,
+    args: Vec<v8::Global<v8::Value>>,
+  ) -> Result<R, Error> {
+    let _span = Span::new("call function");
+    let call = self.inner.call_with_args(func, &args);
+    let result = self
+      .inner
       .with_event_loop_promise(call, PollEventLoopOptions::default())
-      .await?;
-    let mut scope = inner.handle_scope();
-    let local = v8::Local::new(&mut scope, result);
-    Ok(serde_v8::from_v8::<T>(&mut scope, local)?)
+      .await
+      .map_err(to_js_call_error)?;
+    let scope = &mut self.inner.handle_scope();
+    let local = v8::Local::new(scope, result);
+    serde_v8::from_v8::<R>(scope, local)
+      .map_err(|err| anyhow!("Cannot deserialize serde_v8 value: {:#}", err))
+  }
+
+  pub fn execute_script(&mut self, script_name: &'static str, code: String) -> Result<(), Error> {
+    self.inner.execute_script(script_name, code).map(|_| ())
+  }
+}
+
+/// The `on_value` of the current `JsRuntime::call_fn_with_callback` call,
+/// which is stored in an isolate slot for the callback function.
+struct ValueCallback(Box<ValueCallbackFn>);
+
+type ValueCallbackFn = dyn FnMut(&mut v8::HandleScope, v8::Local<v8::Value>) -> Result<(), Error>;
+
+fn call_value_callback(
+  scope: &mut v8::HandleScope,
+  args: v8::FunctionCallbackArguments,
+  _return_value: v8::ReturnValue,
+) {
+  // taken out of the slot while it runs because it needs the scope
+  let result = match scope.remove_slot::<ValueCallback>() {
+    Some(mut callback) => {
+      let result = (callback.0)(scope, args.get(0));
+      scope.set_slot(callback);
+      result
+    }
+    None => Err(anyhow!(
+      "The callback was called after the function returned."
+    )),
+  };
+  if let Err(err) = result {
+    let message = v8::String::new(scope, &format!("{:#}", err)).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    scope.throw_exception(exception);
+  }
+}
+
+/// serde_v8 creates objects without a prototype, so this gives them
+/// `Object.prototype` like `JSON.parse` would. Own `__proto__`
+/// properties are kept as they are.
+fn set_object_prototypes(
+  scope: &mut v8::HandleScope,
+  value: v8::Local<v8::Value>,
+  object_prototype: v8::Local<v8::Value>,
+) {
+  let values = if let Ok(array) = v8::Local::<v8::Array>::try_from(value) {
+    array
+  } else if let Ok(object) = v8::Local::<v8::Object>::try_from(value) {
+    if !object
+      .get_prototype(scope)
+      .is_some_and(|prototype| prototype.is_null())
+    {
+      return;
+    }
+    object.set_prototype(scope, object_prototype);
+    let Some(keys) = object.get_own_property_names(scope, Default::default()) else {
+      return;
+    };
+    let values = v8::Array::new(scope, keys.length() as i32);
+    for index in 0..keys.length() {
+      if let Some(value) = keys
+        .get_index(scope, index)
+        .and_then(|key| object.get(scope, key))
+      {
+        values.set_index(scope, index, value);
+      }
+    }
+    values
+  } else {
+    return;
+  };
+  for index in 0..values.length() {
+    if let Some(value) = values.get_index(scope, index) {
+      set_object_prototypes(scope, value, object_prototype);
+    }
+  }
+}
+
+fn to_fn_args(
+  scope: &mut v8::HandleScope,
+  value: v8::Local<v8::Value>,
+) -> Result<Vec<v8::Global<v8::Value>>, Error> {
+  if value.is_null_or_undefined() {
+    return Ok(Vec::new());
+  }
+  let array = v8::Local::<v8::Array>::try_from(value)
+    .map_err(|_| anyhow!("Expected the arguments to serialize to an array."))?;
+  (0..array.length())
+    .map(|index| {
+      let arg = array
+        .get_index(scope, index)
+        .ok_or_else(|| anyhow!("Failed getting argument {}.", index))?;
+      Ok(v8::Global::new(scope, arg))
+    })
+    .collect()
+}
+
+fn to_js_call_error(err: Error) -> Error {
+  match err.downcast::<JsError>() {
+    Ok(err) => JsCallError::from(err).into(),
+    Err(err) => err,
   }
 }
@@ -1,14 +1,206 @@
-pub fn deserialize_snapshot(data: &[u8]) -> std::io::Result<Box<[u8]>> {
-  // compressing takes about a minute in debug, so only do it in release
-  if cfg!(debug_assertions) {
-    Ok(data.to_vec().into_boxed_slice())
+use deno_core::anyhow::anyhow;
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Error;
+use deno_core::v8;
+
+/// Bytes at the start of every serialized snapshot.
+const MAGIC: &[u8; 4] = b"DPSN";
+/// Version of the header layout. Bump when the layout changes.
+const FORMAT_VERSION: u32 = 3;
+/// The deno_core version snapshots are created with, which must match when
+/// loading one since the snapshot includes deno_core's ops and JS code.
+/// Keep this in sync with the workspace's deno_core dependency.
+pub const DENO_CORE_VERSION: &str = "0.326.0";
+/// The largest uncompressed snapshot that will be loaded. Snapshots are
+/// usually tens of megabytes, so anything larger is likely corrupted.
+pub const MAX_SNAPSHOT_LEN: u64 = 512 * 1024 * 1024;
+
+/// How the snapshot data is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotCompression {
+  None,
+  /// zstd compression at the provided level (1-22). Higher levels produce
+  /// smaller snapshots, but take much longer to create.
+  Zstd {
+    level: i32,
+  },
+}
+
+/// Header written before the snapshot data.
+///
+/// Layout (little endian):
+///
+/// - magic bytes (4 bytes)
+/// - format version (u32)
+/// - V8 version length (u16) followed by the UTF-8 V8 version
+/// - deno_core version length (u16) followed by the UTF-8 deno_core version
+/// - compression kind (u8) where 0 is none and 1 is zstd
+/// - uncompressed snapshot length (u64)
+/// - CRC32 checksum of the preceding header bytes and the data following
+///   the header (u32)
+struct SnapshotHeader<'a> {
+  v8_version: &'a str,
+  deno_core_version: &'a str,
+  is_compressed: bool,
+  uncompressed_len: u64,
+}
+
+impl<'a> SnapshotHeader<'a> {
+  /// Writes the header, which is followed by the checksum.
+  fn write(&self, output: &mut Vec<u8>) {
+    output.extend_from_slice(MAGIC);
+    output.extend(FORMAT_VERSION.to_le_bytes());
+    write_str(output, self.v8_version);
+    write_str(output, self.deno_core_version);
+    output.push(u8::from(self.is_compressed));
+    output.extend(self.uncompressed_len.to_le_bytes());
+  }
+
+  /// Reads the header along with the checksum and the remaining data.
+  fn read(data: &'a [u8]) -> Result<ReadSnapshot<'a>, Error> {
+    let mut reader = ByteReader { data };
+    if reader.take(MAGIC.len())? != MAGIC {
+      bail!("Invalid snapshot. The file is not a snapshot or is corrupted.");
+    }
+    let format_version = u32::from_le_bytes(reader.take_array()?);
+    if format_version != FORMAT_VERSION {
+      bail!(
+        "Unsupported snapshot format version {} (expected {}).",
+        format_version,
+        FORMAT_VERSION
+      );
+    }
+    let v8_version = reader.take_str("V8 version")?;
+    let deno_core_version = reader.take_str("deno_core version")?;
+    let is_compressed = match reader.take_array::<1>()?[0] {
+      0 => false,
+      1 => true,
+      kind => bail!("Invalid snapshot. Unknown compression kind {}.", kind),
+    };
+    let uncompressed_len = u64::from_le_bytes(reader.take_array()?);
+    let header_bytes = &data[..data.len() - reader.data.len()];
+    let checksum = u32::from_le_bytes(reader.take_array()?);
+    Ok(ReadSnapshot {
+      header: SnapshotHeader {
+        v8_version,
+        deno_core_version,
+        is_compressed,
+        uncompressed_len,
+      },
+      header_bytes,
+      checksum,
+      data: reader.data,
+    })
+  }
+}
+
+struct ReadSnapshot<'a> {
+  header: SnapshotHeader<'a>,
+  /// The header bytes before the checksum, which the checksum covers.
+  header_bytes: &'a [u8],
+  checksum: u32,
+  data: &'a [u8],
+}
+
+fn write_str(output: &mut Vec<u8>, value: &str) {
+  output.extend((value.len() as u16).to_le_bytes());
+  output.extend_from_slice(value.as_bytes());
+}
+
+fn compute_checksum(header_bytes: &[u8], data: &[u8]) -> u32 {
+  let mut hasher = crc32fast::Hasher::new();
+  hasher.update(header_bytes);
+  hasher.update(data);
+  hasher.finalize()
+}
+
+struct ByteReader<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+    if self.data.len() < len {
+      bail!("Invalid snapshot. The header was truncated.");
+    }
+    let (taken, rest) = self.data.split_at(len);
+    self.data = rest;
+    Ok(taken)
+  }
+
+  fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+    Ok(self.take(N)?.try_into().unwrap())
+  }
+
+  fn take_str(&mut self, name: &str) -> Result<&'a str, Error> {
+    let len = u16::from_le_bytes(self.take_array()?) as usize;
+    std::str::from_utf8(self.take(len)?)
+      .map_err(|_| anyhow!("Invalid snapshot. The {} was not valid UTF-8.", name))
+  }
+}
+
+/// Serializes the snapshot with a header that's validated when deserializing.
+pub fn serialize_snapshot(snapshot: &[u8], compression: SnapshotCompression) -> Vec<u8> {
+  let data = match compression {
+    SnapshotCompression::None => snapshot.to_vec(),
+    SnapshotCompression::Zstd { level } => {
+      eprintln!("Compressing snapshot (level {})...", level);
+      zstd::bulk::compress(snapshot, level).expect("snapshot compression failed")
+    }
+  };
+  let header = SnapshotHeader {
+    v8_version: v8::V8::get_version(),
+    deno_core_version: DENO_CORE_VERSION,
+    is_compressed: compression != SnapshotCompression::None,
+    uncompressed_len: snapshot.len() as u64,
+  };
+  let mut output = Vec::with_capacity(data.len() + 64);
+  header.write(&mut output);
+  let checksum = compute_checksum(&output, &data);
+  output.extend(checksum.to_le_bytes());
+  output.extend(data);
+  output
+}
+
+pub fn deserialize_snapshot(data: &[u8]) -> Result<Box<[u8]>, Error> {
+  let ReadSnapshot {
+    header,
+    header_bytes,
+    checksum,
+    data,
+  } = SnapshotHeader::read(data)?;
+  if compute_checksum(header_bytes, data) != checksum {
+    bail!("Invalid snapshot. The checksum did not match.");
+  }
+  let v8_version = v8::V8::get_version();
+  if header.v8_version != v8_version {
+    bail!(
+      "Snapshot was created with V8 {}, but this binary uses V8 {}. Rebuild the snapshot.",
+      header.v8_version,
+      v8_version
+    );
+  }
+  if header.deno_core_version != DENO_CORE_VERSION {
+    bail!(
+      "Snapshot was created with deno_core {}, but this binary uses deno_core {}. Rebuild the snapshot.",
+      header.deno_core_version,
+      DENO_CORE_VERSION
+    );
+  }
+  if header.uncompressed_len > MAX_SNAPSHOT_LEN {
+    bail!(
+      "Invalid snapshot. The length of {} bytes exceeds the maximum of {} bytes.",
+      header.uncompressed_len,
+      MAX_SNAPSHOT_LEN
+    );
+  }
+  let snapshot = if header.is_compressed {
+    zstd::bulk::decompress(data, header.uncompressed_len as usize)?
   } else {
-    Ok(
-      zstd::bulk::decompress(
-        &data[4..],
-        u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize,
-      )?
-      .into_boxed_slice(),
-    )
+    data.to_vec()
+  };
+  if snapshot.len() as u64 != header.uncompressed_len {
+    bail!("Invalid snapshot. The length did not match.");
   }
+  Ok(snapshot.into_boxed_slice())
 }
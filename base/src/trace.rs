@@ -0,0 +1,216 @@
+//! Opt-in instrumentation that records spans as Chrome trace events, which
+//! can be viewed in chrome://tracing or https://ui.perfetto.dev
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use deno_core::anyhow::Context;
+use deno_core::anyhow::Error;
+use deno_core::parking_lot::Mutex;
+use serde::Serialize;
+
+pub struct Tracer {
+  start: Instant,
+  writer: Mutex<BufWriter<File>>,
+  file_durations: Mutex<Vec<(Duration, String)>>,
+}
+
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+  name: &'a str,
+  cat: &'static str,
+  ph: &'static str,
+  /// Microseconds since the tracer was created.
+  ts: f64,
+  dur: f64,
+  pid: u32,
+  /// The id of the isolate the span happened in.
+  tid: usize,
+  args: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+impl Tracer {
+  /// Creates a tracer that writes to the file at the path in the
+  /// provided environment variable when it's set.
+  pub fn from_env(env_var_name: &str) -> Result<Option<Tracer>, Error> {
+    match std::env::var_os(env_var_name) {
+      Some(file_path) if !file_path.is_empty() => Ok(Some(Tracer::new(Path::new(&file_path))?)),
+      _ => Ok(None),
+    }
+  }
+
+  pub fn new(file_path: &Path) -> Result<Tracer, Error> {
+    let file = File::create(file_path)
+      .with_context(|| format!("Failed creating trace file {}", file_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    // The closing bracket is optional in the trace event format, which
+    // allows writing the events as they happen.
+    writer.write_all(b"[\n")?;
+    Ok(Tracer {
+      start: Instant::now(),
+      writer: Mutex::new(writer),
+      file_durations: Default::default(),
+    })
+  }
+
+  fn write_event(
+    &self,
+    name: &str,
+    start: Instant,
+    isolate_id: usize,
+    args: &serde_json::Map<String, serde_json::Value>,
+  ) {
+    let ts = start.checked_duration_since(self.start).unwrap_or_default();
+    let event = TraceEvent {
+      name,
+      cat: "dprint",
+      ph: "X",
+      ts: ts.as_secs_f64() * 1_000_000.0,
+      dur: start.elapsed().as_secs_f64() * 1_000_000.0,
+      pid: std::process::id(),
+      tid: isolate_id,
+      args,
+    };
+    let mut writer = self.writer.lock();
+    let _ = serde_json::to_writer(&mut *writer, &event);
+    let _ = writer.write_all(b",\n");
+    let _ = writer.flush();
+  }
+
+  /// Gets a summary of the files that took the longest to format.
+  pub fn slowest_files_text(&self, count: usize) -> String {
+    let mut file_durations = self.file_durations.lock().clone();
+    file_durations.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut text = String::from("Slowest files:\n");
+    for (duration, file_path) in file_durations.iter().take(count) {
+      text.push_str(&format!(
+        "  {:>9.2}ms {}\n",
+        duration.as_secs_f64() * 1000.0,
+        file_path
+      ));
+    }
+    text
+  }
+}
+
+struct ThreadContext {
+  tracer: Arc<Tracer>,
+  isolate_id: usize,
+  /// Arguments to add to the span of the file being formatted.
+  file_args: serde_json::Map<String, serde_json::Value>,
+}
+
+thread_local! {
+  static CONTEXT: RefCell<Option<ThreadContext>> = const { RefCell::new(None) };
+}
+
+/// Sets the tracer that spans created on the current thread are recorded with.
+pub fn set_thread_tracer(tracer: Arc<Tracer>, isolate_id: usize) {
+  CONTEXT.with(|context| {
+    *context.borrow_mut() = Some(ThreadContext {
+      tracer,
+      isolate_id,
+      file_args: Default::default(),
+    });
+  });
+}
+
+pub fn is_enabled() -> bool {
+  CONTEXT.with(|context| context.borrow().is_some())
+}
+
+/// Adds an argument to the span of the file currently
+/// being formatted on this thread.
+pub fn add_file_arg(key: &str, value: impl Into<serde_json::Value>) {
+  CONTEXT.with(|context| {
+    if let Some(context) = context.borrow_mut().as_mut() {
+      context.file_args.insert(key.to_string(), value.into());
+    }
+  });
+}
+
+/// A span that's recorded when dropped if tracing is enabled on the thread.
+pub struct Span {
+  name: &'static str,
+  start: Instant,
+  enabled: bool,
+  file_path: Option<String>,
+  args: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Span {
+  pub fn new(name: &'static str) -> Span {
+    Span::with_start(name, Instant::now())
+  }
+
+  /// Creates a span that started in the past.
+  pub fn with_start(name: &'static str, start: Instant) -> Span {
+    Span {
+      name,
+      start,
+      enabled: is_enabled(),
+      file_path: None,
+      args: Default::default(),
+    }
+  }
+
+  /// Creates the span for formatting a file, which includes the
+  /// arguments added with `add_file_arg`.
+  pub fn file(file_path: &Path) -> Span {
+    Span::file_with_start(file_path, Instant::now())
+  }
+
+  /// Creates the span for a file that started formatting in the past.
+  pub fn file_with_start(file_path: &Path, start: Instant) -> Span {
+    let mut span = Span::with_start("format", start);
+    if span.enabled {
+      let file_path = file_path.to_string_lossy().to_string();
+      span.arg("filePath", file_path.as_str());
+      if let Some(extension) = Path::new(&file_path).extension() {
+        span.arg("extension", extension.to_string_lossy().to_string());
+      }
+      span.file_path = Some(file_path);
+    }
+    span
+  }
+
+  pub fn arg(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+    if self.enabled {
+      self.args.insert(key.to_string(), value.into());
+    }
+  }
+}
+
+impl Drop for Span {
+  fn drop(&mut self) {
+    if !self.enabled {
+      return;
+    }
+    CONTEXT.with(|context| {
+      let mut context = context.borrow_mut();
+      let Some(context) = context.as_mut() else {
+        return;
+      };
+      if let Some(file_path) = self.file_path.take() {
+        self.args.append(&mut context.file_args);
+        context
+          .tracer
+          .file_durations
+          .lock()
+          .push((self.start.elapsed(), file_path));
+      }
+      self
+        .args
+        .insert("isolateId".to_string(), context.isolate_id.into());
+      context
+        .tracer
+        .write_event(self.name, self.start, context.isolate_id, &self.args);
+    });
+  }
+}
@@ -24,3 +24,28 @@ pub fn system_available_memory() -> u64 {
 pub fn set_v8_max_memory(max_memory: usize) {
   deno_core::v8_set_flags(vec![format!("--max-old-space-size={}", max_memory)]);
 }
+
+/// Converts a UTF-8 byte offset in the text to a UTF-16 code unit index,
+/// which is how JavaScript indexes strings.
+pub fn utf8_to_utf16_offset(text: &str, byte_offset: usize) -> usize {
+  let mut utf16_offset = 0;
+  for (index, c) in text.char_indices() {
+    if index >= byte_offset {
+      break;
+    }
+    utf16_offset += c.len_utf16();
+  }
+  utf16_offset
+}
+
+/// Converts a UTF-16 code unit index to a UTF-8 byte offset in the text.
+pub fn utf16_to_utf8_offset(text: &str, utf16_offset: usize) -> usize {
+  let mut current_utf16_offset = 0;
+  for (index, c) in text.char_indices() {
+    if current_utf16_offset >= utf16_offset {
+      return index;
+    }
+    current_utf16_offset += c.len_utf16();
+  }
+  text.len()
+}
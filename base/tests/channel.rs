@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Error;
+use deno_core::futures::future::join_all;
+use deno_core::parking_lot::Mutex;
+use dprint_core::async_runtime::async_trait;
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use dprint_plugin_deno_base::channel::Channel;
+use dprint_plugin_deno_base::channel::CreateChannelOptions;
+use dprint_plugin_deno_base::channel::Formatter;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use pretty_assertions::assert_eq;
+
+fn create_request(index: usize) -> FormatRequest<()> {
+  FormatRequest {
+    config_id: FormatConfigId::from_raw(0),
+    file_path: PathBuf::from(format!("file{}.txt", index)),
+    file_bytes: b"text".to_vec(),
+    config: Arc::new(()),
+    range: None,
+    token: Arc::new(NullCancellationToken),
+  }
+}
+
+/// Records the paths of the files it formats.
+struct RecordingFormatter {
+  file_paths: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+#[async_trait(?Send)]
+impl Formatter<()> for RecordingFormatter {
+  async fn format_text(&mut self, request: FormatRequest<()>) -> Result<Option<Vec<u8>>, Error> {
+    self.file_paths.lock().push(request.file_path);
+    Ok(None)
+  }
+}
+
+#[test]
+fn batches_do_not_starve_large_files() {
+  let file_paths = Arc::new(Mutex::new(Vec::new()));
+  let channel = Channel::new(CreateChannelOptions {
+    // don't allow creating more than one isolate
+    avg_isolate_memory_usage: usize::MAX / 4,
+    memory_per_file_byte: 0,
+    create_formatter_cb: Arc::new({
+      let file_paths = file_paths.clone();
+      move || {
+        // give the other requests time to be queued
+        std::thread::sleep(Duration::from_millis(100));
+        Ok(Box::new(RecordingFormatter {
+          file_paths: file_paths.clone(),
+        }))
+      }
+    }),
+    tracer: None,
+  });
+
+  let mut large_request = create_request(0);
+  large_request.file_path = PathBuf::from("large.txt");
+  large_request.file_bytes = vec![b'a'; 1024 * 1024];
+  create_tokio_runtime().block_on(async {
+    let small_requests = (1..200).map(|index| channel.format(create_request(index)));
+    let requests = std::iter::once(channel.format(create_request(0)))
+      .chain(std::iter::once(channel.format(large_request)))
+      .chain(small_requests);
+    for result in join_all(requests).await {
+      result.unwrap();
+    }
+  });
+
+  let file_paths = file_paths.lock();
+  assert_eq!(file_paths.len(), 201);
+  let large_index = file_paths
+    .iter()
+    .position(|path| path == &PathBuf::from("large.txt"))
+    .unwrap();
+  // formatted after at most one high priority streak of small files
+  assert!(
+    large_index <= 8,
+    "large file was formatted at {}",
+    large_index
+  );
+}
+
+#[test]
+fn failed_isolate_creation_fails_queued_requests() {
+  let attempts = Arc::new(AtomicUsize::new(0));
+  let file_paths = Arc::new(Mutex::new(Vec::new()));
+  let channel = Channel::new(CreateChannelOptions {
+    // don't allow creating more than one isolate
+    avg_isolate_memory_usage: usize::MAX / 4,
+    memory_per_file_byte: 0,
+    create_formatter_cb: Arc::new({
+      let attempts = attempts.clone();
+      let file_paths = file_paths.clone();
+      move || {
+        // give the other requests time to be queued
+        std::thread::sleep(Duration::from_millis(100));
+        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+          bail!("Failed creating the formatter.")
+        }
+        Ok(Box::new(RecordingFormatter {
+          file_paths: file_paths.clone(),
+        }))
+      }
+    }),
+    tracer: None,
+  });
+
+  create_tokio_runtime().block_on(async {
+    let results = join_all((0..4).map(|index| channel.format(create_request(index)))).await;
+    assert_eq!(results.len(), 4);
+    for result in results {
+      assert_eq!(
+        result.unwrap_err().to_string(),
+        "Failed creating the formatter."
+      );
+    }
+
+    // the next requests try creating the isolate again
+    let results = join_all((4..8).map(|index| channel.format(create_request(index)))).await;
+    for result in results {
+      assert_eq!(result.unwrap(), None);
+    }
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(file_paths.lock().len(), 4);
+  });
+}
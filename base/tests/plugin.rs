@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use deno_core::anyhow::Error;
+use deno_core::futures::FutureExt;
+use dprint_core::async_runtime::async_trait;
+use dprint_core::configuration::get_unknown_property_diagnostics;
+use dprint_core::configuration::get_value;
+use dprint_core::configuration::ConfigKeyMap;
+use dprint_core::configuration::ConfigKeyValue;
+use dprint_core::configuration::GlobalConfiguration;
+use dprint_core::plugins::AsyncPluginHandler;
+use dprint_core::plugins::FileMatchingInfo;
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use dprint_core::plugins::PluginInfo;
+use dprint_core::plugins::PluginResolveConfigurationResult;
+use dprint_plugin_deno_base::channel::Formatter;
+use dprint_plugin_deno_base::plugin::JsFormatter;
+use dprint_plugin_deno_base::plugin::JsPluginHandler;
+use dprint_plugin_deno_base::plugin::JsPluginHandlerBuilder;
+use dprint_plugin_deno_base::runtime::CreateRuntimeOptions;
+use dprint_plugin_deno_base::runtime::JsRuntime;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use pretty_assertions::assert_eq;
+use serde::Serialize;
+
+#[derive(Clone, Serialize)]
+struct Config {
+  uppercase: bool,
+}
+
+/// Formats on the isolate threads without a JS runtime.
+struct CaseFormatter;
+
+#[async_trait(?Send)]
+impl Formatter<Config> for CaseFormatter {
+  async fn format_text(
+    &mut self,
+    request: FormatRequest<Config>,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    let text = String::from_utf8(request.file_bytes)?;
+    let formatted = if request.config.uppercase {
+      text.to_uppercase()
+    } else {
+      text.to_lowercase()
+    };
+    Ok((formatted != text).then(|| formatted.into_bytes()))
+  }
+}
+
+fn resolve_config(
+  mut config: ConfigKeyMap,
+  _global_config: GlobalConfiguration,
+) -> PluginResolveConfigurationResult<Config> {
+  let mut diagnostics = Vec::new();
+  let uppercase = get_value(&mut config, "uppercase", true, &mut diagnostics);
+  diagnostics.extend(get_unknown_property_diagnostics(config));
+  PluginResolveConfigurationResult {
+    config: Config { uppercase },
+    diagnostics,
+    file_matching: FileMatchingInfo {
+      file_extensions: vec!["txt".to_string()],
+      file_names: vec![],
+    },
+  }
+}
+
+fn create_handler() -> JsPluginHandler<Config> {
+  create_handler_builder()
+    .formatter(|| Ok(Box::new(CaseFormatter)))
+    .build()
+}
+
+/// Creates a handler with a `JsFormatter` for a bundle that follows
+/// the standard calling convention.
+fn create_js_handler() -> JsPluginHandler<Config> {
+  JsRuntime::initialize_main_thread();
+  create_handler_builder()
+    .formatter(|| {
+      let mut runtime = JsRuntime::new(CreateRuntimeOptions {
+        extensions: vec![],
+        startup_snapshot: None,
+      });
+      runtime.execute_script(
+        "dprint:bundle.js",
+        concat!(
+          "globalThis.dprint = {\n",
+          "  async formatText({ filePath, fileText, config }) {\n",
+          "    if (!filePath.endsWith(\".txt\")) throw new Error(\"Unsupported file.\");\n",
+          "    const formatted = config.uppercase ? fileText.toUpperCase() : fileText.toLowerCase();\n",
+          "    return formatted === fileText ? undefined : formatted;\n",
+          "  },\n",
+          "  async formatTextWithCursor({ fileText, config, cursorOffset }) {\n",
+          "    const formatted = \"> \" + (config.uppercase ? fileText.toUpperCase() : fileText.toLowerCase());\n",
+          "    return { formatted, cursorOffset: cursorOffset + 2 };\n",
+          "  },\n",
+          "};"
+        )
+        .to_string(),
+      )?;
+      Ok(Box::new(JsFormatter::from_runtime(runtime)))
+    })
+    .build()
+}
+
+fn create_handler_builder() -> JsPluginHandlerBuilder<Config> {
+  JsPluginHandlerBuilder::new(
+    PluginInfo {
+      name: "case".to_string(),
+      version: "0.0.0".to_string(),
+      config_key: "case".to_string(),
+      help_url: String::new(),
+      config_schema_url: String::new(),
+      update_url: None,
+    },
+    resolve_config,
+  )
+  .license_text("MIT")
+  .avg_isolate_memory_usage(1024)
+  .memory_per_file_byte(1)
+}
+
+fn create_request(
+  config: &Config,
+  text: &str,
+  range: Option<std::ops::Range<usize>>,
+) -> FormatRequest<Config> {
+  FormatRequest {
+    config_id: FormatConfigId::from_raw(0),
+    file_path: PathBuf::from("file.txt"),
+    file_bytes: text.to_string().into_bytes(),
+    config: Arc::new(config.clone()),
+    range,
+    token: Arc::new(NullCancellationToken),
+  }
+}
+
+#[test]
+fn resolves_config_with_callback() {
+  create_tokio_runtime().block_on(async {
+    let handler = create_handler();
+    assert_eq!(handler.plugin_info().name, "case");
+    assert_eq!(handler.license_text(), "MIT");
+
+    let mut config = ConfigKeyMap::new();
+    config.insert("uppercase".to_string(), ConfigKeyValue::Bool(false));
+    config.insert("unknown".to_string(), ConfigKeyValue::Bool(true));
+    let result = handler.resolve_config(config, Default::default()).await;
+    assert!(!result.config.uppercase);
+    assert_eq!(result.diagnostics.len(), 1);
+    assert_eq!(result.diagnostics[0].property_name, "unknown");
+    assert_eq!(
+      result.file_matching.file_extensions,
+      vec!["txt".to_string()]
+    );
+  });
+}
+
+#[test]
+fn formats_on_channel() {
+  create_tokio_runtime().block_on(async {
+    let handler = create_handler();
+    let config = Config { uppercase: true };
+    let format = |request| handler.format(request, |_| std::future::ready(Ok(None)).boxed_local());
+
+    assert_eq!(
+      format(create_request(&config, "text", None)).await.unwrap(),
+      Some(b"TEXT".to_vec())
+    );
+    assert_eq!(
+      format(create_request(&config, "TEXT", None)).await.unwrap(),
+      None
+    );
+    // range formatting isn't supported, so the file is left alone
+    assert_eq!(
+      format(create_request(&config, "text", Some(0..2)))
+        .await
+        .unwrap(),
+      None
+    );
+  });
+}
+
+#[test]
+fn formats_with_js_formatter() {
+  create_tokio_runtime().block_on(async {
+    let handler = create_js_handler();
+    let config = Config { uppercase: true };
+    let format = |request| handler.format(request, |_| std::future::ready(Ok(None)).boxed_local());
+
+    assert_eq!(
+      format(create_request(&config, "text", None)).await.unwrap(),
+      Some(b"TEXT".to_vec())
+    );
+    assert_eq!(
+      format(create_request(&config, "TEXT", None)).await.unwrap(),
+      None
+    );
+
+    // the cursor offset is converted to and from UTF-16 code units
+    let result = handler
+      .format_with_cursor(create_request(&config, "\u{1F600}ab", None), 5)
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(String::from_utf8(result.text).unwrap(), "> \u{1F600}AB");
+    assert_eq!(result.cursor_offset, 7);
+  });
+}
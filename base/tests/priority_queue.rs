@@ -0,0 +1,94 @@
+use dprint_plugin_deno_base::priority_queue::Priority;
+use dprint_plugin_deno_base::priority_queue::PriorityQueue;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use pretty_assertions::assert_eq;
+
+fn pop_all(queue: &PriorityQueue<&'static str>) -> Vec<&'static str> {
+  std::iter::from_fn(|| queue.try_pop()).collect()
+}
+
+#[test]
+fn high_priority_jumps_ahead() {
+  let queue = PriorityQueue::new(8);
+  queue.push("bulk1", Priority::Normal).unwrap();
+  queue.push("bulk2", Priority::Normal).unwrap();
+  queue.push("editor1", Priority::High).unwrap();
+  queue.push("editor2", Priority::High).unwrap();
+  assert_eq!(
+    pop_all(&queue),
+    vec!["editor1", "editor2", "bulk1", "bulk2"]
+  );
+}
+
+#[test]
+fn normal_priority_is_not_starved() {
+  let queue = PriorityQueue::new(2);
+  for item in ["bulk1", "bulk2"] {
+    queue.push(item, Priority::Normal).unwrap();
+  }
+  for item in ["a", "b", "c", "d", "e"] {
+    queue.push(item, Priority::High).unwrap();
+  }
+  assert_eq!(
+    pop_all(&queue),
+    vec!["a", "b", "bulk1", "c", "d", "bulk2", "e"]
+  );
+}
+
+#[test]
+fn high_streak_only_counts_while_normal_waiting() {
+  let queue = PriorityQueue::new(2);
+  for item in ["a", "b", "c"] {
+    queue.push(item, Priority::High).unwrap();
+  }
+  assert_eq!(pop_all(&queue), vec!["a", "b", "c"]);
+
+  // the previous high priority items shouldn't cause this to go first
+  queue.push("bulk", Priority::Normal).unwrap();
+  queue.push("d", Priority::High).unwrap();
+  assert_eq!(pop_all(&queue), vec!["d", "bulk"]);
+}
+
+#[test]
+fn pop_matching_counts_toward_high_streak() {
+  let queue = PriorityQueue::new(3);
+  queue.push("bulk", Priority::Normal).unwrap();
+  for item in ["a", "b", "c", "d", "e"] {
+    queue.push(item, Priority::High).unwrap();
+  }
+  assert_eq!(queue.try_pop(), Some("a"));
+  // stops at the streak allowance because a normal priority item is waiting
+  assert_eq!(queue.try_pop_matching(10, |_| true), vec!["b", "c"]);
+  assert_eq!(pop_all(&queue), vec!["bulk", "d", "e"]);
+
+  // takes everything when no normal priority items are waiting
+  for item in ["f", "g", "h", "i"] {
+    queue.push(item, Priority::High).unwrap();
+  }
+  assert_eq!(queue.try_pop(), Some("f"));
+  assert_eq!(queue.try_pop_matching(10, |_| true), vec!["g", "h", "i"]);
+}
+
+#[test]
+fn pop_waits_for_push_and_ends_on_close() {
+  let runtime = create_tokio_runtime();
+  runtime.block_on(async {
+    let queue = PriorityQueue::new(8);
+    let pop_items = async {
+      let mut items = Vec::new();
+      while let Some(item) = queue.pop().await {
+        items.push(item);
+      }
+      items
+    };
+    let push_items = async {
+      tokio::task::yield_now().await;
+      queue.push("a", Priority::Normal).unwrap();
+      queue.push("b", Priority::High).unwrap();
+      queue.close();
+      assert_eq!(queue.push("c", Priority::High), Err("c"));
+    };
+    let (items, ()) = tokio::join!(pop_items, push_items);
+    assert_eq!(items, vec!["b", "a"]);
+  });
+}
@@ -0,0 +1,177 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use dprint_plugin_deno_base::runtime::CreateRuntimeOptions;
+use dprint_plugin_deno_base::runtime::JsCallError;
+use dprint_plugin_deno_base::runtime::JsRuntime;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use pretty_assertions::assert_eq;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Args {
+  file_text: String,
+  indent_width: u32,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Output {
+  text: String,
+  count: u32,
+}
+
+fn create_runtime() -> JsRuntime {
+  JsRuntime::initialize_main_thread();
+  let mut runtime = JsRuntime::new(CreateRuntimeOptions {
+    extensions: vec![],
+    startup_snapshot: None,
+  });
+  runtime
+    .execute_script(
+      "dprint:bundle.js",
+      concat!(
+        "globalThis.dprint = {\n",
+        "  async format({ fileText, indentWidth }, suffix) {\n",
+        "    return { text: \" \".repeat(indentWidth) + fileText + suffix, count: arguments.length };\n",
+        "  },\n",
+        "  noArgs() {\n",
+        "    return arguments.length;\n",
+        "  },\n",
+        "  async each(items, onValue) {\n",
+        "    for (const item of items) {\n",
+        "      await Promise.resolve();\n",
+        "      onValue(item * 2);\n",
+        "    }\n",
+        "    return items.length;\n",
+        "  },\n",
+        "  describe(options) {\n",
+        "    return {\n",
+        "      hasObjectPrototype: Object.getPrototypeOf(options) === Object.prototype,\n",
+        "      keys: Object.keys(options).join(\",\"),\n",
+        "    };\n",
+        "  },\n",
+        "  async fail() {\n",
+        "    throw new SyntaxError(\"Unexpected token\");\n",
+        "  },\n",
+        "};"
+      )
+      .to_string(),
+    )
+    .unwrap();
+  runtime
+}
+
+#[test]
+fn call_fn_passes_args_and_resolves_promise() {
+  let tokio_runtime = create_tokio_runtime();
+  let mut runtime = create_runtime();
+  tokio_runtime.block_on(async move {
+    let format = runtime.get_fn("dprint.format").unwrap();
+    let output = runtime
+      .call_fn::<_, Output>(
+        &format,
+        (
+          Args {
+            file_text: "a \"quoted\" text".to_string(),
+            indent_width: 2,
+          },
+          "!",
+        ),
+      )
+      .await
+      .unwrap();
+    assert_eq!(
+      output,
+      Output {
+        text: "  a \"quoted\" text!".to_string(),
+        count: 2,
+      }
+    );
+  });
+}
+
+#[test]
+fn call_fn_without_args() {
+  let tokio_runtime = create_tokio_runtime();
+  let mut runtime = create_runtime();
+  tokio_runtime.block_on(async move {
+    let no_args = runtime.get_fn("dprint.noArgs").unwrap();
+    let count = runtime.call_fn::<_, u32>(&no_args, ()).await.unwrap();
+    assert_eq!(count, 0);
+  });
+}
+
+#[test]
+fn call_fn_converts_exceptions() {
+  let tokio_runtime = create_tokio_runtime();
+  let mut runtime = create_runtime();
+  tokio_runtime.block_on(async move {
+    let fail = runtime.get_fn("dprint.fail").unwrap();
+    let err = runtime.call_fn::<_, ()>(&fail, ()).await.unwrap_err();
+    let err = err.downcast_ref::<JsCallError>().unwrap();
+    assert_eq!(err.name.as_deref(), Some("SyntaxError"));
+    assert_eq!(err.message, "Unexpected token");
+    assert!(err.to_string().starts_with("SyntaxError: Unexpected token"));
+  });
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Description {
+  has_object_prototype: bool,
+  keys: String,
+}
+
+#[test]
+fn call_fn_creates_plain_objects() {
+  let tokio_runtime = create_tokio_runtime();
+  let mut runtime = create_runtime();
+  tokio_runtime.block_on(async move {
+    let describe = runtime.get_fn("dprint.describe").unwrap();
+    let options = serde_json::json!({ "__proto__": null, "semi": true });
+    let description = runtime
+      .call_fn::<_, Description>(&describe, (options,))
+      .await
+      .unwrap();
+    assert_eq!(
+      description,
+      Description {
+        has_object_prototype: true,
+        keys: "__proto__,semi".to_string(),
+      }
+    );
+  });
+}
+
+#[test]
+fn call_fn_with_callback_provides_values() {
+  let tokio_runtime = create_tokio_runtime();
+  let mut runtime = create_runtime();
+  tokio_runtime.block_on(async move {
+    let each = runtime.get_fn("dprint.each").unwrap();
+    let values = Rc::new(RefCell::new(Vec::new()));
+    let count = runtime
+      .call_fn_with_callback::<_, u32, u32>(&each, (vec![1, 2, 3],), {
+        let values = values.clone();
+        move |value| values.borrow_mut().push(value)
+      })
+      .await
+      .unwrap();
+    assert_eq!(count, 3);
+    assert_eq!(values.take(), vec![2, 4, 6]);
+  });
+}
+
+#[test]
+fn get_fn_errors_for_non_functions() {
+  let mut runtime = create_runtime();
+  let err = runtime.get_fn("dprint.missing").unwrap_err();
+  assert_eq!(err.to_string(), "Expected dprint.missing to be a function.");
+  let err = runtime.get_fn("dprint.missing.format").unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "Cannot get dprint.missing.format from a value that's not an object."
+  );
+}
@@ -0,0 +1,76 @@
+use dprint_plugin_deno_base::snapshot::deserialize_snapshot;
+use dprint_plugin_deno_base::snapshot::serialize_snapshot;
+use dprint_plugin_deno_base::snapshot::SnapshotCompression;
+use dprint_plugin_deno_base::snapshot::DENO_CORE_VERSION;
+use dprint_plugin_deno_base::snapshot::MAX_SNAPSHOT_LEN;
+use pretty_assertions::assert_eq;
+
+const SNAPSHOT: &[u8] = b"snapshot data snapshot data snapshot data";
+
+/// Gets the offset of the uncompressed length in the header, which is
+/// followed by the checksum.
+fn uncompressed_len_offset(serialized: &[u8]) -> usize {
+  let mut offset = 8;
+  for _ in 0..2 {
+    let len = u16::from_le_bytes([serialized[offset], serialized[offset + 1]]) as usize;
+    offset += 2 + len;
+  }
+  // skip the compression kind
+  offset + 1
+}
+
+#[test]
+fn round_trips() {
+  for compression in [
+    SnapshotCompression::None,
+    SnapshotCompression::Zstd { level: 3 },
+  ] {
+    let serialized = serialize_snapshot(SNAPSHOT, compression);
+    assert_eq!(&*deserialize_snapshot(&serialized).unwrap(), SNAPSHOT);
+  }
+}
+
+#[test]
+fn checksum_covers_header() {
+  let mut serialized = serialize_snapshot(SNAPSHOT, SnapshotCompression::None);
+  let offset = uncompressed_len_offset(&serialized);
+  serialized[offset] ^= 1;
+  let err = deserialize_snapshot(&serialized).unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "Invalid snapshot. The checksum did not match."
+  );
+}
+
+#[test]
+fn rejects_large_uncompressed_len() {
+  let mut serialized = serialize_snapshot(SNAPSHOT, SnapshotCompression::None);
+  let offset = uncompressed_len_offset(&serialized);
+  serialized[offset..offset + 8].copy_from_slice(&(MAX_SNAPSHOT_LEN + 1).to_le_bytes());
+  // keep the checksum valid so the length is what gets rejected
+  let mut hasher = crc32fast::Hasher::new();
+  hasher.update(&serialized[..offset + 8]);
+  hasher.update(&serialized[offset + 12..]);
+  let checksum = hasher.finalize();
+  serialized[offset + 8..offset + 12].copy_from_slice(&checksum.to_le_bytes());
+  let err = deserialize_snapshot(&serialized).unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    format!(
+      "Invalid snapshot. The length of {} bytes exceeds the maximum of {} bytes.",
+      MAX_SNAPSHOT_LEN + 1,
+      MAX_SNAPSHOT_LEN
+    )
+  );
+}
+
+#[test]
+fn deno_core_version_matches_manifest() {
+  let manifest =
+    std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/../Cargo.toml")).unwrap();
+  let version = manifest
+    .lines()
+    .find_map(|line| line.strip_prefix("deno_core = "))
+    .unwrap();
+  assert_eq!(version.trim_matches('"'), DENO_CORE_VERSION);
+}
@@ -1,40 +1,82 @@
-import { format, getSupportInfo, type Options, type Plugin, type SupportLanguage } from "prettier";
-import * as pluginJsDoc from "prettier-plugin-jsdoc";
-import * as pluginSvelte from "prettier-plugin-svelte";
-import * as pluginAcorn from "prettier/plugins/acorn";
-import * as pluginAngular from "prettier/plugins/angular";
-import * as pluginBabel from "prettier/plugins/babel";
-import * as pluginEstree from "prettier/plugins/estree";
-import * as pluginFlow from "prettier/plugins/flow";
-import * as pluginGlimmer from "prettier/plugins/glimmer";
-import * as pluginGraphQl from "prettier/plugins/graphql";
-import * as pluginHtml from "prettier/plugins/html";
-import * as pluginMarkdown from "prettier/plugins/markdown";
-import * as pluginMeriyah from "prettier/plugins/meriyah";
-import * as pluginPostCss from "prettier/plugins/postcss";
-import * as pluginTypeScript from "prettier/plugins/typescript";
-import * as pluginYaml from "prettier/plugins/yaml";
-
-const plugins: Plugin[] = [
-  pluginTypeScript,
-  pluginBabel,
-  pluginAcorn,
-  pluginAngular,
-  pluginEstree,
-  pluginFlow,
-  pluginGlimmer,
-  pluginGraphQl,
-  pluginHtml,
-  pluginMarkdown,
-  pluginMeriyah,
-  pluginPostCss,
-  pluginYaml,
-  pluginSvelte,
+import { format, formatWithCursor, getSupportInfo, type Options, type Plugin, type SupportLanguage } from "prettier";
+
+// Plugins are loaded with `require` on first use rather than imported so that
+// their code is stored in the snapshot, but not evaluated. This keeps the heap
+// of each isolate small when it only formats a few languages.
+
+/** Prettier's language plugins in the order they're provided to Prettier. */
+const languagePluginLoaders: [string, () => Plugin][] = [
+  ["typescript", () => require("prettier/plugins/typescript")],
+  ["babel", () => require("prettier/plugins/babel")],
+  ["acorn", () => require("prettier/plugins/acorn")],
+  ["angular", () => require("prettier/plugins/angular")],
+  ["estree", () => require("prettier/plugins/estree")],
+  ["flow", () => require("prettier/plugins/flow")],
+  ["glimmer", () => require("prettier/plugins/glimmer")],
+  ["graphql", () => require("prettier/plugins/graphql")],
+  ["html", () => require("prettier/plugins/html")],
+  ["markdown", () => require("prettier/plugins/markdown")],
+  ["meriyah", () => require("prettier/plugins/meriyah")],
+  ["postcss", () => require("prettier/plugins/postcss")],
+  ["yaml", () => require("prettier/plugins/yaml")],
+];
+
+/**
+ * Parsers for languages that never format embedded code of other languages,
+ * mapped to the language plugins they need. Files using these parsers only
+ * load the listed plugins. None of the bundled plugins change how these
+ * parsers format, so they're not loaded either.
+ */
+const isolatedParserPlugins: { [parser: string]: string[] } = {
+  // front matter is formatted as yaml
+  css: ["postcss", "yaml"],
+  less: ["postcss", "yaml"],
+  scss: ["postcss", "yaml"],
+  yaml: ["yaml"],
+  graphql: ["graphql"],
+  glimmer: ["glimmer"],
+  json: ["babel", "estree"],
+  json5: ["babel", "estree"],
+  jsonc: ["babel", "estree"],
+  "json-stringify": ["babel", "estree"],
+};
+
+function getLanguagePlugins(names?: Set<string>) {
+  const plugins: Plugin[] = [];
+  for (const [name, load] of languagePluginLoaders) {
+    if (names == null || names.has(name)) {
+      plugins.push(load());
+    }
+  }
+  return plugins;
+}
+
+interface BundledPlugin {
+  name: string;
+  load: () => Plugin;
+  defaultEnabled: boolean;
+}
+
+/** Bundled plugins that can be toggled with `"plugin.<name>": true|false`. */
+const bundledPlugins: BundledPlugin[] = [
+  { name: "svelte", load: () => require("prettier-plugin-svelte"), defaultEnabled: true },
+  { name: "jsDoc", load: () => require("prettier-plugin-jsdoc"), defaultEnabled: false },
+  { name: "astro", load: () => require("prettier-plugin-astro"), defaultEnabled: false },
 ];
 
+/** Parsers that may be inferred for a file extension. Set by `setExtensionParsers`. */
+let extensionParsers: { [extension: string]: string[] } = {};
+
 (globalThis as any).dprint = {
   getExtensions,
+  getExtensionParsers,
+  setExtensionParsers,
+  getBundledPlugins,
   formatText,
+  formatTexts,
+  formatTextWithCursor,
+  releaseConfig,
+  inferParser,
 };
 
 async function getExtensions() {
@@ -43,7 +85,7 @@ async function getExtensions() {
   for (const language of supportInfo.languages) {
     addForLanguage(language);
   }
-  for (const plugin of plugins) {
+  for (const plugin of getLanguagePlugins()) {
     for (const language of plugin.languages ?? []) {
       addForLanguage(language);
     }
@@ -51,28 +93,104 @@ async function getExtensions() {
   return Array.from(set.values());
 
   function addForLanguage(language: SupportLanguage) {
-    for (const ext of language.extensions ?? []) {
-      set.add(ext.replace(/^\./, ""));
+    for (const ext of getLanguageExtensions(language)) {
+      set.add(ext);
+    }
+  }
+}
+
+/** Gets the parsers each file extension may be inferred as. This loads every language plugin. */
+async function getExtensionParsers() {
+  const result: { [extension: string]: string[] } = {};
+  for (const plugin of getLanguagePlugins()) {
+    for (const language of plugin.languages ?? []) {
+      for (const ext of getLanguageExtensions(language)) {
+        const parsers = result[ext] ?? (result[ext] = []);
+        for (const parser of language.parsers) {
+          if (parsers.indexOf(parser) === -1) {
+            parsers.push(parser);
+          }
+        }
+      }
     }
   }
+  return result;
+}
+
+function setExtensionParsers(value: { [extension: string]: string[] }) {
+  extensionParsers = value;
+}
+
+async function getBundledPlugins() {
+  return bundledPlugins.map(({ name, load, defaultEnabled }) => {
+    const plugin = load();
+    return {
+      name,
+      defaultEnabled,
+      extensions: getPluginLanguageExtensions(plugin),
+      options: getPluginOptions(plugin),
+    };
+  });
+}
+
+function getPluginOptions(plugin: Plugin) {
+  const options = plugin.options ?? {};
+  return Object.keys(options).map(name => {
+    const option = options[name];
+    return {
+      name,
+      type: option.type,
+      array: (option as { array?: boolean }).array ?? false,
+      choices: option.type === "choice" ? option.choices.map(choice => choice.value) : [],
+    };
+  });
+}
+
+function getPluginLanguageExtensions(plugin: Plugin) {
+  const extensions: string[] = [];
+  for (const language of plugin.languages ?? []) {
+    extensions.push(...getLanguageExtensions(language));
+  }
+  return extensions;
+}
+
+/** Gets the parser Prettier infers for the file. Only used when tracing. */
+async function inferParser(filePath: string) {
+  const fileName = filePath.replace(/^.*[\\/]/, "").toLowerCase();
+  const supportInfo = await getSupportInfo({ plugins: bundledPlugins.map(plugin => plugin.load()) });
+  const language = supportInfo.languages.find(language =>
+    (language.filenames ?? []).some(name => name.toLowerCase() === fileName)
+  ) ?? supportInfo.languages.find(language =>
+    (language.extensions ?? []).some(ext => fileName.endsWith(ext.toLowerCase()))
+  );
+  return language?.parsers[0] ?? null;
+}
+
+function getLanguageExtensions(language: SupportLanguage) {
+  return (language.extensions ?? []).map(ext => ext.replace(/^\./, ""));
 }
 
 interface FormatTextOptions {
   filePath: string;
   fileText: string;
-  config: Options;
-  pluginsConfig: PluginsConfig;
+  /** Key of the resolved config in the form `<configId>:<extension>`. */
+  configKey: string;
+  /** Only provided the first time a config key is used in this isolate. */
+  config?: Options;
+  pluginsConfig?: PluginsConfig;
 }
 
 interface PluginsConfig {
-  js_doc: boolean;
+  /** Names of the enabled bundled plugins. */
+  enabled: string[];
 }
 
-async function formatText({ filePath, fileText, config, pluginsConfig }: FormatTextOptions) {
+const resolvedConfigs = new Map<string, Options>();
+
+async function formatText({ filePath, fileText, configKey, config, pluginsConfig }: FormatTextOptions) {
   const formattedText = await format(fileText, {
     filepath: filePath,
-    plugins: getPlugins(pluginsConfig),
-    ...config,
+    ...getResolvedConfig(configKey, filePath, config, pluginsConfig),
   });
   if (formattedText === fileText) {
     return undefined;
@@ -81,10 +199,123 @@ async function formatText({ filePath, fileText, config, pluginsConfig }: FormatT
   }
 }
 
-function getPlugins(pluginsConfig: PluginsConfig) {
-  if (pluginsConfig.js_doc) {
-    return [...plugins, pluginJsDoc as Plugin<any>];
+interface FormatTextsResult {
+  index: number;
+  formatted?: string;
+  error?: string;
+  durationMs: number;
+}
+
+/**
+ * Formats several files at once, keeping any error with the file it occurred in.
+ * Each result is passed to `onResult` as soon as the file is formatted.
+ */
+async function formatTexts(optionsList: FormatTextOptions[], onResult: (result: FormatTextsResult) => void) {
+  for (let index = 0; index < optionsList.length; index++) {
+    const start = Date.now();
+    let result: { formatted?: string; error?: string };
+    try {
+      result = { formatted: await formatText(optionsList[index]) };
+    } catch (err) {
+      result = { error: String((err as Error)?.stack ?? err) };
+    }
+    onResult({ index, ...result, durationMs: Date.now() - start });
+  }
+}
+
+interface FormatTextWithCursorOptions extends FormatTextOptions {
+  /** UTF-16 index of the cursor in the file text. */
+  cursorOffset: number;
+}
+
+async function formatTextWithCursor(
+  { filePath, fileText, cursorOffset, configKey, config, pluginsConfig }: FormatTextWithCursorOptions,
+) {
+  const result = await formatWithCursor(fileText, {
+    filepath: filePath,
+    ...getResolvedConfig(configKey, filePath, config, pluginsConfig),
+    cursorOffset,
+  });
+  if (result.formatted === fileText) {
+    return undefined;
   } else {
-    return plugins;
+    return {
+      formatted: result.formatted,
+      cursorOffset: result.cursorOffset,
+    };
+  }
+}
+
+function getResolvedConfig(
+  configKey: string,
+  filePath: string,
+  config: Options | undefined,
+  pluginsConfig: PluginsConfig | undefined,
+) {
+  if (config != null && pluginsConfig != null) {
+    // the config key includes the extension, so the plugins can be cached with it
+    const resolvedConfig: Options = {
+      plugins: getPlugins(pluginsConfig, getFileParsers(filePath, config)),
+      ...config,
+    };
+    resolvedConfigs.set(configKey, resolvedConfig);
+    return resolvedConfig;
+  }
+  const resolvedConfig = resolvedConfigs.get(configKey);
+  if (resolvedConfig == null) {
+    throw new Error(`Config was not provided for key: ${configKey}`);
+  }
+  return resolvedConfig;
+}
+
+function releaseConfig(configId: number) {
+  const prefix = `${configId}:`;
+  for (const key of resolvedConfigs.keys()) {
+    if (key.startsWith(prefix)) {
+      resolvedConfigs.delete(key);
+    }
+  }
+}
+
+function getFileParsers(filePath: string, config: Options) {
+  if (typeof config.parser === "string") {
+    return [config.parser];
+  }
+  const index = filePath.lastIndexOf(".");
+  const ext = index === -1 ? undefined : filePath.slice(index + 1).toLowerCase();
+  return ext == null ? [] : extensionParsers[ext] ?? [];
+}
+
+function getPlugins(pluginsConfig: PluginsConfig, parsers: string[]) {
+  const isolatedPluginNames = getIsolatedPluginNames(parsers);
+  if (isolatedPluginNames != null) {
+    return getLanguagePlugins(isolatedPluginNames);
+  }
+
+  const enabledNames = new Set(pluginsConfig.enabled);
+  const enabledPlugins = getLanguagePlugins();
+  for (const bundledPlugin of bundledPlugins) {
+    if (enabledNames.has(bundledPlugin.name)) {
+      enabledPlugins.push(bundledPlugin.load());
+    }
+  }
+  return enabledPlugins;
+}
+
+/** Gets the language plugins needed when every parser is isolated. */
+function getIsolatedPluginNames(parsers: string[]) {
+  if (parsers.length === 0) {
+    return undefined;
+  }
+  const names = new Set<string>();
+  for (const parser of parsers) {
+    const parserPlugins = isolatedParserPlugins[parser];
+    if (parserPlugins == null) {
+      return undefined;
+    }
+    for (const name of parserPlugins) {
+      names.add(name);
+    }
   }
+  return names;
 }
@@ -6,35 +6,17 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
-use deno_core::Extension;
-use dprint_plugin_deno_base::runtime::CreateRuntimeOptions;
-use dprint_plugin_deno_base::runtime::JsRuntime;
-use dprint_plugin_deno_base::util::create_tokio_runtime;
+use dprint_plugin_deno_base::build::BuildPluginOptions;
+use dprint_plugin_deno_base::snapshot::SnapshotCompression;
 
 fn main() {
   let crate_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
   let root_dir = crate_dir.parent().unwrap();
   let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
-  let startup_snapshot_path = out_dir.join("STARTUP_SNAPSHOT.bin");
   let js_dir = root_dir.join("js");
-  let supported_extensions_path = out_dir.join("SUPPORTED_EXTENSIONS.json");
-
-  eprintln!("Running JS build...");
-  let build_result = Command::new(if cfg!(windows) { "npm.cmd" } else { "npm" })
-    .args(["run", "build:script"])
-    .current_dir(js_dir.join("node"))
-    .status();
-  match build_result {
-    Ok(status) => {
-      if status.code() != Some(0) {
-        panic!("Error building.");
-      }
-    }
-    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-      eprintln!("Skipping build because npm executable not found.");
-    }
-    Err(err) => panic!("Error building to script: {}", err),
-  }
+  let embedded_packages_path = out_dir.join("EMBEDDED_PACKAGES.json");
+  let third_party_licenses_path = out_dir.join("THIRD_PARTY_LICENSES.txt");
+  let node_dir = js_dir.join("node");
 
   // ensure the build is invalidated if any of these files change
   println!(
@@ -53,66 +35,303 @@ fn main() {
     "cargo:rerun-if-changed={}",
     js_dir.join("node/shims/url.js").display()
   );
+  println!("cargo:rerun-if-env-changed={}", PREBUILT_BUNDLE_DIR_ENV_VAR);
+  println!(
+    "cargo:rerun-if-env-changed={}",
+    PREBUILT_BUNDLE_SHA256_ENV_VAR
+  );
 
-  let startup_code_path = js_dir.join("node/dist/main.js");
-  if !startup_code_path.exists() {
-    panic!("Run `cd js/node && npm run build:script` first.");
-  }
-  let snapshot = create_snapshot(startup_snapshot_path.clone(), &startup_code_path);
-  let snapshot = Box::leak(snapshot);
-
-  // serialize the supported extensions
-  eprintln!("Creating runtime...");
-  let tokio_runtime = create_tokio_runtime();
-  JsRuntime::initialize_main_thread();
-  let mut runtime = JsRuntime::new(CreateRuntimeOptions {
-    extensions: vec![
+  std::fs::write(
+    embedded_packages_path,
+    deno_core::serde_json::to_string(&get_embedded_packages(&node_dir)).unwrap(),
+  )
+  .unwrap();
+
+  let (startup_code_path, third_party_licenses) = match env::var_os(PREBUILT_BUNDLE_DIR_ENV_VAR) {
+    Some(prebuilt_dir) => {
+      // relative paths are resolved from the repository root
+      let prebuilt_dir = root_dir.join(prebuilt_dir);
+      eprintln!("Using prebuilt bundle at {}", prebuilt_dir.display());
+      let startup_code_path = prebuilt_dir.join("main.js");
+      verify_prebuilt_bundle(&startup_code_path);
+      let licenses_path = prebuilt_dir.join("THIRD_PARTY_LICENSES.txt");
+      println!("cargo:rerun-if-changed={}", licenses_path.display());
+      // required because the packages in node_modules may not be available to
+      // create it, which would leave the license text incomplete
+      let third_party_licenses = std::fs::read_to_string(&licenses_path).unwrap_or_else(|err| {
+        panic!(
+          "Error reading {}. A prebuilt bundle must include the licenses of its npm packages, which is created by scripts/create_prebuilt_bundle.ts: {}",
+          licenses_path.display(),
+          err
+        )
+      });
+      (startup_code_path, third_party_licenses)
+    }
+    None => {
+      run_js_build(&node_dir);
+      let startup_code_path = node_dir.join("dist/main.js");
+      if !startup_code_path.exists() {
+        panic!("Run `cd js/node && npm run build:script` first.");
+      }
+      let third_party_licenses = get_third_party_licenses_text(&node_dir);
+      // store beside the bundle so it can be vendored along with it
+      std::fs::write(
+        node_dir.join("dist/THIRD_PARTY_LICENSES.txt"),
+        &third_party_licenses,
+      )
+      .unwrap();
+      (startup_code_path, third_party_licenses)
+    }
+  };
+  std::fs::write(third_party_licenses_path, third_party_licenses).unwrap();
+
+  dprint_plugin_deno_base::build::build_plugin(BuildPluginOptions {
+    cargo_manifest_dir: env!("CARGO_MANIFEST_DIR"),
+    out_dir,
+    bundle_path: startup_code_path,
+    snapshot_extensions: vec![
+      deno_webidl::deno_webidl::init_ops_and_esm(),
+      deno_console::deno_console::init_ops_and_esm(),
+      deno_url::deno_url::init_ops_and_esm(),
+      main::init_ops_and_esm(),
+    ],
+    runtime_extensions: vec![
       deno_webidl::deno_webidl::init_ops(),
       deno_console::deno_console::init_ops(),
       deno_url::deno_url::init_ops(),
       main::init_ops(),
     ],
-    startup_snapshot: Some(snapshot),
+    metadata_fns: vec![
+      ("BUNDLED_PLUGINS.json", "getBundledPlugins"),
+      ("EXTENSION_PARSERS.json", "getExtensionParsers"),
+    ],
+    compression: get_snapshot_compression(),
   });
+}
 
-  eprintln!("Getting extensions...");
-  let file_extensions: Vec<String> = tokio_runtime.block_on(async move {
-    let startup_text = get_startup_text(&startup_code_path);
-    runtime
-      .execute_script("dprint:prettier.js", startup_text.clone())
-      .unwrap();
-    runtime
-      .execute_async_fn::<Vec<String>>("deno:get_extensions.js", "dprint.getExtensions".to_string())
-      .await
-      .unwrap()
-  });
-  std::fs::write(
-    supported_extensions_path,
-    deno_core::serde_json::to_string(&file_extensions).unwrap(),
-  )
-  .unwrap();
-  eprintln!("Done");
+/// Directory containing a prebuilt `main.js` bundle to use instead of running npm.
+const PREBUILT_BUNDLE_DIR_ENV_VAR: &str = "DPRINT_PRETTIER_PREBUILT_BUNDLE_DIR";
+/// Expected SHA-256 checksum of the prebuilt bundle. When not set, the
+/// checksum is read from `main.js.sha256` in the prebuilt bundle directory.
+const PREBUILT_BUNDLE_SHA256_ENV_VAR: &str = "DPRINT_PRETTIER_PREBUILT_BUNDLE_SHA256";
+
+fn run_js_build(node_dir: &Path) {
+  eprintln!("Running JS build...");
+  let build_result = Command::new(if cfg!(windows) { "npm.cmd" } else { "npm" })
+    .args(["run", "build:script"])
+    .current_dir(node_dir)
+    .status();
+  match build_result {
+    Ok(status) => {
+      if status.code() != Some(0) {
+        panic!("Error building.");
+      }
+    }
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+      eprintln!("Skipping build because npm executable not found.");
+    }
+    Err(err) => panic!("Error building to script: {}", err),
+  }
 }
 
-fn create_snapshot(snapshot_path: PathBuf, startup_code_path: &Path) -> Box<[u8]> {
-  let startup_text = get_startup_text(startup_code_path);
-  dprint_plugin_deno_base::build::create_snapshot(
-    dprint_plugin_deno_base::build::CreateSnapshotOptions {
-      cargo_manifest_dir: env!("CARGO_MANIFEST_DIR"),
-      snapshot_path,
-      extensions: extensions(),
-      with_runtime_cb: Some(Box::new(move |runtime| {
-        runtime
-          .execute_script("dprint:prettier.js", startup_text.clone())
-          .unwrap();
-      })),
-      warmup_script: None,
-    },
-  )
+fn verify_prebuilt_bundle(bundle_path: &Path) {
+  println!("cargo:rerun-if-changed={}", bundle_path.display());
+  let bundle_bytes = std::fs::read(bundle_path)
+    .unwrap_or_else(|err| panic!("Error reading {}: {}", bundle_path.display(), err));
+  let expected_checksum = match env::var(PREBUILT_BUNDLE_SHA256_ENV_VAR) {
+    Ok(checksum) => checksum,
+    Err(_) => {
+      let checksum_path = bundle_path.with_extension("js.sha256");
+      println!("cargo:rerun-if-changed={}", checksum_path.display());
+      std::fs::read_to_string(&checksum_path).unwrap_or_else(|err| {
+        panic!(
+          "Error reading {}. Provide the checksum in that file or with {}: {}",
+          checksum_path.display(),
+          PREBUILT_BUNDLE_SHA256_ENV_VAR,
+          err
+        )
+      })
+    }
+  };
+  // allow the output format of sha256sum (`<checksum>  <file name>`)
+  let expected_checksum = expected_checksum
+    .split_whitespace()
+    .next()
+    .unwrap_or_default()
+    .to_lowercase();
+  let actual_checksum = sha256::digest(bundle_bytes.as_slice());
+  if actual_checksum != expected_checksum {
+    panic!(
+      "Checksum mismatch for {}.\n  Expected: {}\n  Actual: {}",
+      bundle_path.display(),
+      expected_checksum,
+      actual_checksum
+    );
+  }
 }
 
-fn get_startup_text(startup_code_path: &Path) -> String {
-  std::fs::read_to_string(startup_code_path).unwrap()
+/// Gets the locked versions of Prettier and the bundled Prettier plugins.
+fn get_embedded_packages(node_dir: &Path) -> Vec<deno_core::serde_json::Value> {
+  let read_json = |file_name: &str| -> deno_core::serde_json::Value {
+    let text = std::fs::read_to_string(node_dir.join(file_name)).unwrap();
+    deno_core::serde_json::from_str(&text).unwrap()
+  };
+  let package_json = read_json("package.json");
+  let package_lock = read_json("package-lock.json");
+  package_json["dependencies"]
+    .as_object()
+    .unwrap()
+    .keys()
+    .filter(|name| *name == "prettier" || name.starts_with("prettier-plugin-"))
+    .map(|name| {
+      let version = package_lock["packages"][format!("node_modules/{}", name)]["version"]
+        .as_str()
+        .unwrap_or_else(|| panic!("Could not find {} in package-lock.json", name));
+      deno_core::serde_json::json!({
+        "name": name,
+        "version": version,
+      })
+    })
+    .collect()
+}
+
+/// Packages only used to create the bundle and not included in it.
+const BUILD_ONLY_PACKAGES: [&str; 3] = ["@types/node", "parcel", "typescript"];
+
+/// Gets the license texts of the npm packages that may be included in
+/// the bundle, which is every package reachable from the dependencies
+/// in package.json other than the build tools.
+fn get_third_party_licenses_text(node_dir: &Path) -> String {
+  let read_json = |path: &Path| -> Option<deno_core::serde_json::Value> {
+    let text = std::fs::read_to_string(path).ok()?;
+    deno_core::serde_json::from_str(&text).ok()
+  };
+  let package_json = read_json(&node_dir.join("package.json")).unwrap();
+  let package_lock = read_json(&node_dir.join("package-lock.json")).unwrap();
+  let packages = package_lock["packages"].as_object().unwrap();
+
+  let mut pending = ["dependencies", "devDependencies"]
+    .iter()
+    .filter_map(|key| package_json[key].as_object())
+    .flat_map(|deps| deps.keys())
+    .filter(|name| !BUILD_ONLY_PACKAGES.contains(&name.as_str()))
+    .map(|name| format!("node_modules/{}", name))
+    .collect::<Vec<_>>();
+  let mut package_paths = std::collections::BTreeSet::new();
+  while let Some(package_path) = pending.pop() {
+    if !package_paths.insert(package_path.clone()) {
+      continue;
+    }
+    let Some(deps) = packages
+      .get(&package_path)
+      .and_then(|package| package["dependencies"].as_object())
+    else {
+      continue;
+    };
+    for dep_name in deps.keys() {
+      match resolve_lock_package(packages, &package_path, dep_name) {
+        Some(dep_path) => pending.push(dep_path),
+        None => panic!("Could not resolve {} from {}", dep_name, package_path),
+      }
+    }
+  }
+
+  let mut text = String::new();
+  for package_path in package_paths {
+    let lock_package = &packages[&package_path];
+    let package_dir = node_dir.join(&package_path);
+    let name = package_path.rsplit_once("node_modules/").unwrap().1;
+    let version = lock_package["version"].as_str().unwrap_or("unknown");
+    let license = lock_package["license"]
+      .as_str()
+      .map(|license| license.to_string())
+      .or_else(|| {
+        read_json(&package_dir.join("package.json")).and_then(|package| {
+          package["license"]
+            .as_str()
+            .map(|license| license.to_string())
+        })
+      })
+      .unwrap_or_else(|| "unknown license".to_string());
+    text.push_str(&"=".repeat(80));
+    text.push_str(&format!("\n{} {} ({})\n", name, version, license));
+    text.push_str(&"=".repeat(80));
+    text.push_str("\n\n");
+    match read_license_file(&package_dir) {
+      Some(license_text) => text.push_str(license_text.trim_end()),
+      None => {
+        println!(
+          "cargo:warning=Could not find license file for {}. Run `npm ci` in js/node.",
+          name
+        );
+        text.push_str("License file not found.");
+      }
+    }
+    text.push_str("\n\n");
+  }
+  text
+}
+
+/// Resolves a dependency the same way node does, by looking in the nested
+/// node_modules folders before the ancestor ones.
+fn resolve_lock_package(
+  packages: &deno_core::serde_json::Map<String, deno_core::serde_json::Value>,
+  from_path: &str,
+  name: &str,
+) -> Option<String> {
+  let mut base = from_path;
+  loop {
+    let candidate = format!("{}/node_modules/{}", base, name);
+    if packages.contains_key(&candidate) {
+      return Some(candidate);
+    }
+    match base.rfind("/node_modules/") {
+      Some(index) => base = &base[..index],
+      None => break,
+    }
+  }
+  let candidate = format!("node_modules/{}", name);
+  packages.contains_key(&candidate).then_some(candidate)
+}
+
+fn read_license_file(package_dir: &Path) -> Option<String> {
+  let mut entries = std::fs::read_dir(package_dir)
+    .ok()?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| {
+      let file_name = entry.file_name().to_string_lossy().to_lowercase();
+      file_name.starts_with("license") || file_name.starts_with("licence")
+    })
+    .map(|entry| entry.path())
+    .collect::<Vec<_>>();
+  entries.sort();
+  std::fs::read_to_string(entries.first()?).ok()
+}
+
+/// Compression level to use for the snapshot, where 0 disables compression.
+const SNAPSHOT_COMPRESSION_LEVEL_ENV_VAR: &str = "DPRINT_PRETTIER_SNAPSHOT_COMPRESSION_LEVEL";
+
+fn get_snapshot_compression() -> SnapshotCompression {
+  println!(
+    "cargo:rerun-if-env-changed={}",
+    SNAPSHOT_COMPRESSION_LEVEL_ENV_VAR
+  );
+  match env::var(SNAPSHOT_COMPRESSION_LEVEL_ENV_VAR) {
+    Ok(level) => match level.trim().parse::<i32>() {
+      Ok(0) => SnapshotCompression::None,
+      Ok(level) => SnapshotCompression::Zstd { level },
+      Err(_) => panic!(
+        "Expected a number for {}, but was: {}",
+        SNAPSHOT_COMPRESSION_LEVEL_ENV_VAR, level
+      ),
+    },
+    // compressing at the max level takes about a minute, so only do it by
+    // default for the profile of the binary being built (not this build script)
+    Err(_) => match env::var("PROFILE").as_deref() {
+      Ok("release") => SnapshotCompression::Zstd { level: 22 },
+      _ => SnapshotCompression::None,
+    },
+  }
 }
 
 deno_core::extension!(
@@ -123,12 +342,3 @@ deno_core::extension!(
     "main.js",
   ]
 );
-
-fn extensions() -> Vec<Extension> {
-  vec![
-    deno_webidl::deno_webidl::init_ops_and_esm(),
-    deno_console::deno_console::init_ops_and_esm(),
-    deno_url::deno_url::init_ops_and_esm(),
-    main::init_ops_and_esm(),
-  ]
-}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::Weak;
+
+use deno_core::anyhow::Error;
+use deno_core::futures::FutureExt;
+use deno_core::parking_lot::Mutex;
+use dprint_core::plugins::AsyncPluginHandler;
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+
+use crate::config::PrettierConfig;
+use crate::handler::PrettierPluginHandler;
+
+/// Formats files with Prettier from Rust without going through dprint.
+///
+/// The isolates are pooled and reused between calls, so create this once
+/// and share it. Use [`format_text`] to use a shared instance.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), deno_core::anyhow::Error> {
+/// use dprint_plugin_prettier::Prettier;
+/// use dprint_plugin_prettier::config::PrettierConfigBuilder;
+///
+/// let config = PrettierConfigBuilder::new().option("singleQuote", true).build()?;
+/// let prettier = Prettier::default();
+/// let formatted = prettier.format_text("file.ts", "const   v = \"\"", &config).await?;
+/// assert_eq!(formatted.as_deref(), Some("const v = '';\n"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Prettier {
+  handler: PrettierPluginHandler,
+  configs: Mutex<ConfigIds>,
+}
+
+/// Ids for the configurations that are in use, which allows the
+/// isolates to reuse the options they've resolved for a configuration.
+#[derive(Default)]
+struct ConfigIds {
+  /// Keyed on the address of the configuration. The weak reference keeps the
+  /// allocation, so the address isn't reused while the entry exists.
+  ids: HashMap<usize, (FormatConfigId, Weak<PrettierConfig>)>,
+  next_id: u32,
+}
+
+impl Prettier {
+  /// Formats the text as if it were the file at the provided path, which
+  /// is used to infer the parser.
+  ///
+  /// Returns `None` when the text is already formatted.
+  pub async fn format_text(
+    &self,
+    file_path: impl AsRef<Path>,
+    file_text: &str,
+    config: &Arc<PrettierConfig>,
+  ) -> Result<Option<String>, Error> {
+    let config_id = self.get_config_id(config);
+    let result = self
+      .handler
+      .format(
+        FormatRequest {
+          config_id,
+          file_path: file_path.as_ref().to_path_buf(),
+          file_bytes: file_text.as_bytes().to_vec(),
+          config: config.clone(),
+          range: None,
+          token: Arc::new(NullCancellationToken),
+        },
+        |_| std::future::ready(Ok(None)).boxed_local(),
+      )
+      .await?;
+    Ok(result.map(String::from_utf8).transpose()?)
+  }
+
+  fn get_config_id(&self, config: &Arc<PrettierConfig>) -> FormatConfigId {
+    let mut configs = self.configs.lock();
+    let key = Arc::as_ptr(config) as usize;
+    if let Some((config_id, _)) = configs.ids.get(&key) {
+      return *config_id;
+    }
+    // forget the configurations that were dropped
+    configs
+      .ids
+      .retain(|_, (_, config)| config.strong_count() > 0);
+    configs.next_id += 1;
+    let config_id = FormatConfigId::from_raw(configs.next_id);
+    configs.ids.insert(key, (config_id, Arc::downgrade(config)));
+    config_id
+  }
+}
+
+/// Formats the text as if it were the file at the provided path using
+/// a shared [`Prettier`] instance.
+///
+/// Returns `None` when the text is already formatted.
+pub async fn format_text(
+  file_path: impl AsRef<Path>,
+  file_text: &str,
+  config: &Arc<PrettierConfig>,
+) -> Result<Option<String>, Error> {
+  static PRETTIER: OnceLock<Prettier> = OnceLock::new();
+  PRETTIER
+    .get_or_init(Prettier::default)
+    .format_text(file_path, file_text, config)
+    .await
+}
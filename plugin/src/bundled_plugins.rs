@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// An optional Prettier plugin that's included in the snapshot.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundledPlugin {
+  /// Name used in the `"plugin.<name>"` configuration key.
+  pub name: String,
+  pub default_enabled: bool,
+  /// File extensions the plugin adds support for.
+  pub extensions: Vec<String>,
+  /// Options the plugin declares in addition to Prettier's own.
+  pub options: Vec<BundledPluginOption>,
+}
+
+#[derive(Deserialize)]
+pub struct BundledPluginOption {
+  pub name: String,
+  #[serde(rename = "type")]
+  pub kind: BundledPluginOptionKind,
+  pub array: bool,
+  /// Allowed values when the kind is `Choice`.
+  pub choices: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BundledPluginOptionKind {
+  Boolean,
+  Int,
+  Choice,
+  String,
+  Path,
+  /// A kind this plugin doesn't know how to validate.
+  #[serde(other)]
+  Unknown,
+}
+
+impl BundledPluginOption {
+  pub fn is_valid_value(&self, value: &serde_json::Value) -> bool {
+    match self.kind {
+      BundledPluginOptionKind::Boolean => value.is_boolean(),
+      BundledPluginOptionKind::Int => value.is_i64() || value.is_u64(),
+      BundledPluginOptionKind::Choice => self.choices.contains(value),
+      BundledPluginOptionKind::String | BundledPluginOptionKind::Path => value.is_string(),
+      BundledPluginOptionKind::Unknown => true,
+    }
+  }
+
+  pub fn expected_text(&self) -> String {
+    let text = match self.kind {
+      BundledPluginOptionKind::Boolean => "a boolean".to_string(),
+      BundledPluginOptionKind::Int => "an integer".to_string(),
+      BundledPluginOptionKind::Choice => format!(
+        "one of {}",
+        self
+          .choices
+          .iter()
+          .map(|choice| choice.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+      BundledPluginOptionKind::String | BundledPluginOptionKind::Path => "a string".to_string(),
+      BundledPluginOptionKind::Unknown => "a value".to_string(),
+    };
+    if self.array {
+      format!("an array where each value is {}", text)
+    } else {
+      text
+    }
+  }
+}
+
+pub fn get_bundled_plugins() -> &'static Vec<BundledPlugin> {
+  static BUNDLED_PLUGINS: OnceLock<Vec<BundledPlugin>> = OnceLock::new();
+  BUNDLED_PLUGINS.get_or_init(|| {
+    let json_bytes = include_bytes!(concat!(env!("OUT_DIR"), "/BUNDLED_PLUGINS.json"));
+
+    deno_core::serde_json::from_slice(json_bytes).unwrap()
+  })
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::config::PrettierConfig;
+use crate::formatter::get_extension;
+use crate::formatter::get_extension_parsers;
+use crate::formatter::resolve_config;
+use crate::versions::get_versions_text;
+
+/// Versions of the plugin and embedded packages, which are part of every key.
+fn get_cached_versions_text() -> &'static str {
+  static VERSIONS_TEXT: OnceLock<String> = OnceLock::new();
+  VERSIONS_TEXT.get_or_init(get_versions_text)
+}
+
+/// Marks a cache entry for a file that's already formatted.
+const UNCHANGED: u8 = 0;
+/// Marks a cache entry that's followed by the formatted text.
+const FORMATTED: u8 = 1;
+
+/// Formatting results stored on disk in `"cacheDir"`, keyed by a hash of
+/// everything that affects the output. This allows sharing the results
+/// between checkouts, unlike dprint's incremental formatting.
+pub struct ResultCache {
+  dir: PathBuf,
+}
+
+impl ResultCache {
+  pub fn new(dir: impl Into<PathBuf>) -> Self {
+    Self { dir: dir.into() }
+  }
+
+  /// Gets the key of the result of formatting the file.
+  pub fn key(&self, file_path: &Path, file_bytes: &[u8], config: &PrettierConfig) -> String {
+    let file_name = file_path
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+    let extension = get_extension(&file_name).unwrap_or_default();
+    let options = serde_json::Value::Object(resolve_config(&extension, config).into_owned());
+    // the parser is inferred from the file name and the enabled plugins,
+    // so both are included along with the parsers it may be inferred as
+    let mut enabled_plugins = config.plugins.enabled.clone();
+    enabled_plugins.sort();
+    let enabled_plugins = enabled_plugins.join(",");
+    let parsers = get_extension_parsers()
+      .get(&extension)
+      .map(|parsers| parsers.join(","))
+      .unwrap_or_default();
+
+    let mut data = Vec::new();
+    for part in [
+      get_cached_versions_text().as_bytes(),
+      file_name.as_bytes(),
+      options.to_string().as_bytes(),
+      enabled_plugins.as_bytes(),
+      parsers.as_bytes(),
+      file_bytes,
+    ] {
+      data.extend((part.len() as u64).to_le_bytes());
+      data.extend(part);
+    }
+    sha256::digest(data.as_slice())
+  }
+
+  /// Gets the cached result, which is `Some(None)` when the
+  /// file was already formatted.
+  pub fn get(&self, key: &str) -> Option<Option<Vec<u8>>> {
+    let mut bytes = std::fs::read(self.entry_path(key)).ok()?;
+    match bytes.first() {
+      Some(&UNCHANGED) if bytes.len() == 1 => Some(None),
+      Some(&FORMATTED) => {
+        bytes.remove(0);
+        Some(Some(bytes))
+      }
+      _ => None,
+    }
+  }
+
+  /// Stores the result. Failures are ignored since the cache is only an
+  /// optimization.
+  pub fn set(&self, key: &str, result: Option<&[u8]>) {
+    let entry_path = self.entry_path(key);
+    let Some(parent) = entry_path.parent() else {
+      return;
+    };
+    if std::fs::create_dir_all(parent).is_err() {
+      return;
+    }
+    let mut bytes = Vec::with_capacity(result.map(|text| text.len()).unwrap_or(0) + 1);
+    match result {
+      Some(text) => {
+        bytes.push(FORMATTED);
+        bytes.extend(text);
+      }
+      None => bytes.push(UNCHANGED),
+    }
+    // write to a temporary file then rename so that another process
+    // never reads a partially written entry
+    let temp_path = entry_path.with_extension(format!("tmp{}", std::process::id()));
+    if std::fs::write(&temp_path, bytes).is_ok()
+      && std::fs::rename(&temp_path, &entry_path).is_err()
+    {
+      let _ = std::fs::remove_file(&temp_path);
+    }
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    self.dir.join(&key[..2]).join(key)
+  }
+}
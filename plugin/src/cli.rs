@@ -0,0 +1,221 @@
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Context;
+use deno_core::anyhow::Error;
+use deno_core::futures::future::join_all;
+use deno_core::futures::FutureExt;
+use dprint_core::configuration::ConfigKeyMap;
+use dprint_core::plugins::AsyncPluginHandler;
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use dprint_plugin_prettier::config::check_diagnostics;
+use dprint_plugin_prettier::config::PrettierConfig;
+use dprint_plugin_prettier::versions::get_versions_text;
+use dprint_plugin_prettier::PrettierPluginHandler;
+
+const USAGE: &str = "Usage:
+  dprint-plugin-prettier format [--config <path>] <files...>
+  dprint-plugin-prettier check [--config <path>] <files...>
+  dprint-plugin-prettier --stdin --stdin-filepath <path> [--config <path>]
+  dprint-plugin-prettier --versions
+
+The config file is JSON in the same shape as the \"prettier\" section of a dprint config file.";
+
+pub enum CliCommand {
+  /// Formats the files in place.
+  Format(Vec<PathBuf>),
+  /// Reports the files that aren't formatted.
+  Check(Vec<PathBuf>),
+  /// Formats stdin as if it were the file at the provided path.
+  Stdin { file_path: PathBuf },
+  /// Outputs the versions of the embedded npm packages.
+  Versions,
+}
+
+pub struct CliArgs {
+  pub command: CliCommand,
+  pub config_path: Option<PathBuf>,
+}
+
+/// Parses the arguments for using the plugin as a standalone CLI.
+///
+/// Returns `None` when the arguments aren't for the CLI, which
+/// is the case when dprint launches the process plugin.
+pub fn parse_cli_args(args: &[String]) -> Result<Option<CliArgs>, Error> {
+  if args.first().is_some_and(|arg| arg == "--versions") {
+    return Ok(Some(CliArgs {
+      command: CliCommand::Versions,
+      config_path: None,
+    }));
+  }
+
+  let is_stdin = args.iter().any(|arg| arg == "--stdin");
+  let command_name = match args.first() {
+    Some(arg) if arg == "format" || arg == "check" => Some(arg.as_str()),
+    _ if is_stdin => None,
+    _ => return Ok(None),
+  };
+
+  let mut config_path = None;
+  let mut stdin_file_path = None;
+  let mut file_paths = Vec::new();
+  let mut args = args.iter().skip(usize::from(command_name.is_some()));
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--config" => config_path = Some(PathBuf::from(take_value(&mut args, arg)?)),
+      "--stdin-filepath" => stdin_file_path = Some(PathBuf::from(take_value(&mut args, arg)?)),
+      "--stdin" => {}
+      "-h" | "--help" => bail!("{}", USAGE),
+      arg if arg.starts_with('-') => bail!("Unknown flag: {}\n\n{}", arg, USAGE),
+      arg => file_paths.push(PathBuf::from(arg)),
+    }
+  }
+
+  let command = match command_name {
+    Some("format") => CliCommand::Format(file_paths),
+    Some("check") => CliCommand::Check(file_paths),
+    _ => {
+      if !file_paths.is_empty() {
+        bail!("Files cannot be provided with --stdin.\n\n{}", USAGE);
+      }
+      match stdin_file_path {
+        Some(file_path) => CliCommand::Stdin { file_path },
+        None => bail!("--stdin-filepath is required with --stdin.\n\n{}", USAGE),
+      }
+    }
+  };
+  Ok(Some(CliArgs {
+    command,
+    config_path,
+  }))
+}
+
+fn take_value<'a>(
+  args: &mut impl Iterator<Item = &'a String>,
+  flag: &str,
+) -> Result<&'a str, Error> {
+  match args.next() {
+    Some(value) => Ok(value),
+    None => bail!("Expected a value for {}.\n\n{}", flag, USAGE),
+  }
+}
+
+/// Runs the CLI, returning the exit code.
+pub async fn run_cli(args: CliArgs) -> Result<i32, Error> {
+  let handler = PrettierPluginHandler::default();
+  let config = resolve_config(&handler, args.config_path).await?;
+
+  match args.command {
+    CliCommand::Versions => {
+      print!("{}", get_versions_text());
+      Ok(0)
+    }
+    CliCommand::Stdin { file_path } => {
+      let mut file_text = String::new();
+      std::io::stdin().read_to_string(&mut file_text)?;
+      let result =
+        format_file(&handler, &config, file_path, file_text.clone().into_bytes()).await?;
+      let output = result.unwrap_or_else(|| file_text.into_bytes());
+      std::io::stdout().write_all(&output)?;
+      Ok(0)
+    }
+    CliCommand::Format(file_paths) | CliCommand::Check(file_paths) if file_paths.is_empty() => {
+      bail!("Expected at least one file.\n\n{}", USAGE)
+    }
+    CliCommand::Format(file_paths) => {
+      let mut exit_code = 0;
+      for (file_path, result) in format_files(&handler, &config, file_paths).await {
+        match result {
+          Ok(Some(formatted)) => {
+            std::fs::write(&file_path, formatted)
+              .with_context(|| format!("Failed writing {}", file_path.display()))?;
+            println!("Formatted {}", file_path.display());
+          }
+          Ok(None) => {}
+          Err(err) => {
+            eprintln!("Error formatting {}: {:#}", file_path.display(), err);
+            exit_code = 1;
+          }
+        }
+      }
+      Ok(exit_code)
+    }
+    CliCommand::Check(file_paths) => {
+      let mut exit_code = 0;
+      for (file_path, result) in format_files(&handler, &config, file_paths).await {
+        match result {
+          Ok(Some(_)) => {
+            println!("Not formatted: {}", file_path.display());
+            exit_code = 1;
+          }
+          Ok(None) => {}
+          Err(err) => {
+            eprintln!("Error formatting {}: {:#}", file_path.display(), err);
+            exit_code = 1;
+          }
+        }
+      }
+      Ok(exit_code)
+    }
+  }
+}
+
+async fn resolve_config(
+  handler: &PrettierPluginHandler,
+  config_path: Option<PathBuf>,
+) -> Result<Arc<PrettierConfig>, Error> {
+  let config: ConfigKeyMap = match config_path {
+    Some(config_path) => {
+      let text = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("Failed reading {}", config_path.display()))?;
+      serde_json::from_str(&text)
+        .with_context(|| format!("Failed deserializing {}", config_path.display()))?
+    }
+    None => Default::default(),
+  };
+  let result = handler.resolve_config(config, Default::default()).await;
+  check_diagnostics(&result.diagnostics)?;
+  Ok(Arc::new(result.config))
+}
+
+async fn format_files(
+  handler: &PrettierPluginHandler,
+  config: &Arc<PrettierConfig>,
+  file_paths: Vec<PathBuf>,
+) -> Vec<(PathBuf, Result<Option<Vec<u8>>, Error>)> {
+  // format concurrently so the channel can spread the work over multiple isolates
+  join_all(file_paths.into_iter().map(|file_path| async move {
+    let result = match std::fs::read(&file_path) {
+      Ok(file_bytes) => format_file(handler, config, file_path.clone(), file_bytes).await,
+      Err(err) => Err(Error::from(err).context(format!("Failed reading {}", file_path.display()))),
+    };
+    (file_path, result)
+  }))
+  .await
+}
+
+async fn format_file(
+  handler: &PrettierPluginHandler,
+  config: &Arc<PrettierConfig>,
+  file_path: PathBuf,
+  file_bytes: Vec<u8>,
+) -> Result<Option<Vec<u8>>, Error> {
+  handler
+    .format(
+      FormatRequest {
+        config_id: FormatConfigId::from_raw(0),
+        file_path,
+        file_bytes,
+        config: config.clone(),
+        range: None,
+        token: Arc::new(NullCancellationToken),
+      },
+      |_| std::future::ready(Ok(None)).boxed_local(),
+    )
+    .await
+}
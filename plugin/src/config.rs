@@ -1,15 +1,64 @@
+use std::sync::Arc;
+
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Error;
 use dprint_core::configuration::get_nullable_value;
 use dprint_core::configuration::get_value;
 use dprint_core::configuration::ConfigKeyMap;
 use dprint_core::configuration::ConfigKeyValue;
+use dprint_core::configuration::ConfigurationDiagnostic;
 use dprint_core::configuration::GlobalConfiguration;
 use dprint_core::configuration::NewLineKind;
 use dprint_core::configuration::ResolveConfigurationResult;
 use serde::Serialize;
 
-#[derive(Clone, Serialize, Default)]
+use crate::bundled_plugins::get_bundled_plugins;
+
+#[derive(Clone, Serialize)]
 pub struct PrettierPluginConfig {
-  pub js_doc: bool,
+  /// Names of the enabled bundled plugins.
+  pub enabled: Vec<String>,
+}
+
+impl Default for PrettierPluginConfig {
+  fn default() -> Self {
+    Self {
+      enabled: get_bundled_plugins()
+        .iter()
+        .filter(|plugin| plugin.default_enabled)
+        .map(|plugin| plugin.name.clone())
+        .collect(),
+    }
+  }
+}
+
+impl PrettierPluginConfig {
+  pub fn is_enabled(&self, name: &str) -> bool {
+    self.enabled.iter().any(|enabled| enabled == name)
+  }
+}
+
+/// What to do with files larger than `"maxFileSize"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum MaxFileSizeBehavior {
+  /// Leave the file as-is and log a warning.
+  #[default]
+  Skip,
+  /// Fail formatting the file.
+  Error,
+}
+
+impl std::str::FromStr for MaxFileSizeBehavior {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "skip" => Ok(MaxFileSizeBehavior::Skip),
+      "error" => Ok(MaxFileSizeBehavior::Error),
+      _ => Err("Expected \"skip\" or \"error\".".to_string()),
+    }
+  }
 }
 
 #[derive(Clone, Serialize, Default)]
@@ -17,6 +66,83 @@ pub struct PrettierConfig {
   pub main: serde_json::Map<String, serde_json::Value>,
   pub extension_overrides: serde_json::Map<String, serde_json::Value>,
   pub plugins: PrettierPluginConfig,
+  /// Logs a warning when formatting a file takes longer than this.
+  pub slow_file_warning_ms: Option<u32>,
+  /// Files larger than this many bytes aren't formatted.
+  pub max_file_size: Option<usize>,
+  pub max_file_size_behavior: MaxFileSizeBehavior,
+  /// Skips files that are generated or minified.
+  pub skip_generated_files: bool,
+  /// Text near the start of a file that marks it as generated.
+  pub generated_file_markers: Vec<String>,
+  /// Directory to cache formatting results in.
+  pub cache_dir: Option<String>,
+}
+
+/// Builds a [`PrettierConfig`] when using this crate from Rust. The options
+/// are the same as in the `"prettier"` section of a dprint config file.
+#[derive(Default)]
+pub struct PrettierConfigBuilder {
+  config: serde_json::Map<String, serde_json::Value>,
+  global_config: GlobalConfiguration,
+}
+
+impl PrettierConfigBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets a Prettier option (ex. `"singleQuote"`), an option of a bundled
+  /// plugin, or an option of this plugin (ex. `"maxFileSize"`).
+  pub fn option(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+    self.config.insert(name.to_string(), value.into());
+    self
+  }
+
+  /// Sets an option for only the files with the extension.
+  pub fn extension_option(
+    self,
+    extension: &str,
+    name: &str,
+    value: impl Into<serde_json::Value>,
+  ) -> Self {
+    self.option(&format!("{}.{}", extension, name), value)
+  }
+
+  /// Enables or disables a bundled plugin (ex. `"jsDoc"`).
+  pub fn plugin(self, name: &str, enabled: bool) -> Self {
+    self.option(&format!("plugin.{}", name), enabled)
+  }
+
+  /// Sets dprint's global configuration, which provides the
+  /// defaults for some options (ex. `"lineWidth"`).
+  pub fn global_config(mut self, global_config: GlobalConfiguration) -> Self {
+    self.global_config = global_config;
+    self
+  }
+
+  /// Resolves the configuration, erroring when any option is invalid.
+  ///
+  /// The configuration is returned in an `Arc` so that formatting with
+  /// it again can reuse the options resolved in the isolates.
+  pub fn build(self) -> Result<Arc<PrettierConfig>, Error> {
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::Value::Object(self.config))?;
+    let result = resolve_config(config, self.global_config);
+    check_diagnostics(&result.diagnostics)?;
+    Ok(Arc::new(result.config))
+  }
+}
+
+/// Errors with the messages of the diagnostics when there are any.
+pub fn check_diagnostics(diagnostics: &[ConfigurationDiagnostic]) -> Result<(), Error> {
+  if !diagnostics.is_empty() {
+    let messages = diagnostics
+      .iter()
+      .map(|diagnostic| format!("  {}: {}", diagnostic.property_name, diagnostic.message))
+      .collect::<Vec<_>>();
+    bail!("Configuration has diagnostics:\n{}", messages.join("\n"));
+  }
+  Ok(())
 }
 
 pub fn resolve_config(
@@ -27,9 +153,23 @@ pub fn resolve_config(
   let mut main: serde_json::Map<String, serde_json::Value> = Default::default();
   let mut extension_overrides: serde_json::Map<String, serde_json::Value> = Default::default();
 
-  let plugins = PrettierPluginConfig {
-    js_doc: get_value(&mut config, "plugin.jsDoc", false, &mut diagnostics),
-  };
+  let plugins = resolve_plugins_config(&mut config, &mut diagnostics);
+  let slow_file_warning_ms = get_nullable_value(&mut config, "slowFileWarningMs", &mut diagnostics);
+  let max_file_size = get_nullable_value(&mut config, "maxFileSize", &mut diagnostics);
+  let max_file_size_behavior = get_value(
+    &mut config,
+    "maxFileSizeBehavior",
+    MaxFileSizeBehavior::Skip,
+    &mut diagnostics,
+  );
+  let cache_dir = get_nullable_value(&mut config, "cacheDir", &mut diagnostics);
+  let skip_generated_files = get_value(&mut config, "skipGeneratedFiles", false, &mut diagnostics);
+  let generated_file_markers = get_string_array_value(
+    &mut config,
+    "generatedFileMarkers",
+    vec!["@generated".to_string()],
+    &mut diagnostics,
+  );
 
   let dprint_line_width = get_value(
     &mut config,
@@ -91,6 +231,18 @@ pub fn resolve_config(
 
   for (key, value) in config {
     let value = config_key_value_to_json(value);
+    let option_name = key
+      .rfind('.')
+      .map(|index| &key[index + 1..])
+      .unwrap_or(&key);
+    if option_name == "__proto__" {
+      diagnostics.push(ConfigurationDiagnostic {
+        message: "Reserved property name.".to_string(),
+        property_name: key,
+      });
+      continue;
+    }
+    validate_plugin_option(&key, option_name, &value, &plugins, &mut diagnostics);
     if let Some(index) = key.rfind('.') {
       let extension = key[..index].to_lowercase();
       let key = &key[index + 1..];
@@ -110,11 +262,127 @@ pub fn resolve_config(
       main,
       extension_overrides,
       plugins,
+      slow_file_warning_ms,
+      max_file_size,
+      max_file_size_behavior,
+      skip_generated_files,
+      generated_file_markers,
+      cache_dir,
     },
     diagnostics,
   }
 }
 
+fn get_string_array_value(
+  config: &mut ConfigKeyMap,
+  key: &str,
+  default_value: Vec<String>,
+  diagnostics: &mut Vec<ConfigurationDiagnostic>,
+) -> Vec<String> {
+  let Some(value) = config.shift_remove(key) else {
+    return default_value;
+  };
+  let values = match value {
+    ConfigKeyValue::Array(values) => values
+      .into_iter()
+      .map(|value| match value {
+        ConfigKeyValue::String(value) => Some(value),
+        _ => None,
+      })
+      .collect::<Option<Vec<_>>>(),
+    _ => None,
+  };
+  values.unwrap_or_else(|| {
+    diagnostics.push(ConfigurationDiagnostic {
+      property_name: key.to_string(),
+      message: "Expected an array of strings.".to_string(),
+    });
+    default_value
+  })
+}
+
+fn resolve_plugins_config(
+  config: &mut ConfigKeyMap,
+  diagnostics: &mut Vec<ConfigurationDiagnostic>,
+) -> PrettierPluginConfig {
+  let bundled_plugins = get_bundled_plugins();
+  let mut enabled = Vec::new();
+  for plugin in bundled_plugins {
+    let key = format!("plugin.{}", plugin.name);
+    if get_value(config, &key, plugin.default_enabled, diagnostics) {
+      enabled.push(plugin.name.clone());
+    }
+  }
+
+  let unknown_keys = config
+    .keys()
+    .filter(|key| key.starts_with("plugin."))
+    .cloned()
+    .collect::<Vec<_>>();
+  for key in unknown_keys {
+    config.shift_remove(&key);
+    diagnostics.push(ConfigurationDiagnostic {
+      message: format!(
+        "Unknown bundled plugin. Expected one of: {}",
+        bundled_plugins
+          .iter()
+          .map(|plugin| plugin.name.as_str())
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+      property_name: key,
+    });
+  }
+
+  PrettierPluginConfig { enabled }
+}
+
+/// Validates options declared by the bundled plugins, which Prettier
+/// would otherwise silently ignore when they're invalid.
+fn validate_plugin_option(
+  property_name: &str,
+  option_name: &str,
+  value: &serde_json::Value,
+  plugins: &PrettierPluginConfig,
+  diagnostics: &mut Vec<ConfigurationDiagnostic>,
+) {
+  let Some((plugin, option)) = get_bundled_plugins().iter().find_map(|plugin| {
+    plugin
+      .options
+      .iter()
+      .find(|option| option.name == option_name)
+      .map(|option| (plugin, option))
+  }) else {
+    return;
+  };
+
+  let is_valid = if option.array {
+    value
+      .as_array()
+      .is_some_and(|values| values.iter().all(|value| option.is_valid_value(value)))
+  } else {
+    option.is_valid_value(value)
+  };
+  if !is_valid {
+    diagnostics.push(ConfigurationDiagnostic {
+      property_name: property_name.to_string(),
+      message: format!(
+        "Expected {} for option of plugin '{}'.",
+        option.expected_text(),
+        plugin.name
+      ),
+    });
+  } else if !plugins.is_enabled(&plugin.name) {
+    diagnostics.push(ConfigurationDiagnostic {
+      property_name: property_name.to_string(),
+      message: format!(
+        "Has no effect because the '{}' plugin is disabled. Enable it with \"plugin.{}\": true",
+        plugin.name, plugin.name
+      ),
+    });
+  }
+}
+
 fn config_key_value_to_json(value: ConfigKeyValue) -> serde_json::Value {
   match value {
     ConfigKeyValue::Bool(value) => value.into(),
@@ -1,56 +1,317 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
 use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 
+use deno_core::anyhow::anyhow;
 use deno_core::anyhow::Error;
 use deno_core::serde_json;
 use dprint_core::async_runtime::async_trait;
+use dprint_core::plugins::FormatConfigId;
 use dprint_core::plugins::FormatRequest;
+use dprint_plugin_deno_base::channel::BatchResult;
+use dprint_plugin_deno_base::channel::FormatWithCursorResult;
 use dprint_plugin_deno_base::channel::Formatter;
+use dprint_plugin_deno_base::channel::OnBatchResult;
+use dprint_plugin_deno_base::plugin::StartupSnapshot;
 use dprint_plugin_deno_base::runtime::CreateRuntimeOptions;
+use dprint_plugin_deno_base::runtime::HeapStatistics;
 use dprint_plugin_deno_base::runtime::JsRuntime;
-use dprint_plugin_deno_base::snapshot::deserialize_snapshot;
-use dprint_plugin_deno_base::util::set_v8_max_memory;
+use dprint_plugin_deno_base::trace;
+use dprint_plugin_deno_base::trace::Span;
+use dprint_plugin_deno_base::util::utf16_to_utf8_offset;
+use dprint_plugin_deno_base::util::utf8_to_utf16_offset;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
 
 use crate::config::PrettierConfig;
+use crate::slow_files::SlowFiles;
 
-fn get_startup_snapshot() -> &'static [u8] {
-  // Copied from Deno's codebase:
-  // https://github.com/denoland/deno/blob/daa7c6d32ab5a4029f8084e174d621f5562256be/cli/tsc.rs#L55
-  static STARTUP_SNAPSHOT: OnceLock<Box<[u8]>> = OnceLock::new();
-
-  STARTUP_SNAPSHOT.get_or_init(
-    #[cold]
-    #[inline(never)]
-    || {
-      // Also set the v8 max memory at the same time. This was added because
-      // on the DefinitelyTyped repo there would be some OOM errors after formatting
-      // for a while and this solved that for some reason.
-      set_v8_max_memory(512);
-
-      static COMPRESSED_COMPILER_SNAPSHOT: &[u8] =
-        include_bytes!(concat!(env!("OUT_DIR"), "/STARTUP_SNAPSHOT.bin"));
-
-      deserialize_snapshot(COMPRESSED_COMPILER_SNAPSHOT).unwrap()
-    },
-  )
+// The v8 max memory is set when loading the snapshot. This was added because
+// on the DefinitelyTyped repo there would be some OOM errors after formatting
+// for a while and this solved that for some reason.
+static STARTUP_SNAPSHOT: StartupSnapshot = StartupSnapshot::new(include_bytes!(concat!(
+  env!("OUT_DIR"),
+  "/STARTUP_SNAPSHOT.bin"
+)))
+.with_v8_max_memory(512);
+
+static EXTENSION_PARSERS_JSON: &str =
+  include_str!(concat!(env!("OUT_DIR"), "/EXTENSION_PARSERS.json"));
+
+/// Gets the Prettier parsers that may be used for each supported extension.
+pub fn get_extension_parsers() -> &'static HashMap<String, Vec<String>> {
+  static EXTENSION_PARSERS: OnceLock<HashMap<String, Vec<String>>> = OnceLock::new();
+  EXTENSION_PARSERS.get_or_init(|| serde_json::from_str(EXTENSION_PARSERS_JSON).unwrap())
 }
 
 pub struct PrettierFormatter {
   runtime: JsRuntime,
+  configs: HashMap<FormatConfigId, LoadedConfig>,
+  slow_files: Arc<SlowFiles>,
+}
+
+/// A configuration that has been sent to the isolate.
+struct LoadedConfig {
+  /// Held to detect when dprint sends a new configuration for the same id.
+  config: Arc<PrettierConfig>,
+  /// Extensions whose resolved options are cached on the JS side.
+  extensions: HashSet<String>,
 }
 
-impl Default for PrettierFormatter {
-  fn default() -> Self {
-    let runtime = JsRuntime::new(CreateRuntimeOptions {
+impl PrettierFormatter {
+  pub fn new() -> Result<Self, Error> {
+    Self::with_slow_files(Default::default())
+  }
+
+  /// Creates a formatter that reports slow files to the provided
+  /// set, which may be shared with other formatters.
+  pub fn with_slow_files(slow_files: Arc<SlowFiles>) -> Result<Self, Error> {
+    let mut runtime = JsRuntime::new(CreateRuntimeOptions {
       extensions: vec![
         deno_webidl::deno_webidl::init_ops(),
         deno_console::deno_console::init_ops(),
         deno_url::deno_url::init_ops(),
       ],
-      startup_snapshot: Some(&get_startup_snapshot()),
+      startup_snapshot: Some(STARTUP_SNAPSHOT.get()?),
     });
-    Self { runtime }
+    // used to only load the language plugins needed for a file
+    runtime.execute_script(
+      "dprint:set_extension_parsers.js",
+      format!("dprint.setExtensionParsers({});", EXTENSION_PARSERS_JSON),
+    )?;
+    Ok(Self {
+      runtime,
+      configs: Default::default(),
+      slow_files,
+    })
+  }
+
+  /// Creates the arguments object for a call to `dprint.formatText`.
+  fn create_format_args(
+    &mut self,
+    request: FormatRequest<PrettierConfig>,
+  ) -> Result<FormatArgs, Error> {
+    let file_path = request.file_path.to_string_lossy();
+    let extension = get_extension(&file_path).unwrap_or_default();
+    let config_id = request.config_id.as_raw();
+    let mut release_config_id = None;
+    let mut args = serde_json::Map::new();
+    args.insert("filePath".to_string(), file_path.as_ref().into());
+    args.insert(
+      "fileText".to_string(),
+      String::from_utf8(request.file_bytes)?.into(),
+    );
+    args.insert(
+      "configKey".to_string(),
+      format!("{}:{}", config_id, extension).into(),
+    );
+
+    let is_stale_config = self
+      .configs
+      .get(&request.config_id)
+      .is_some_and(|loaded| !Arc::ptr_eq(&loaded.config, &request.config));
+    if is_stale_config {
+      // dprint sent a new configuration, so drop anything cached for the old one
+      self.configs.remove(&request.config_id);
+      release_config_id = Some(config_id);
+    }
+    let loaded_config = self
+      .configs
+      .entry(request.config_id)
+      .or_insert_with(|| LoadedConfig {
+        config: request.config.clone(),
+        extensions: Default::default(),
+      });
+    let mut sent_config = None;
+    if !loaded_config.extensions.contains(&extension) {
+      let config = &request.config;
+      args.insert(
+        "config".to_string(),
+        serde_json::Value::Object(resolve_config(&extension, config).into_owned()),
+      );
+      args.insert(
+        "pluginsConfig".to_string(),
+        serde_json::to_value(&config.plugins).unwrap(),
+      );
+      sent_config = Some(SentConfig {
+        config_id: request.config_id,
+        config: request.config,
+        extension,
+      });
+    }
+
+    Ok(FormatArgs {
+      release_config_id,
+      sent_config,
+      args,
+    })
+  }
+
+  /// Marks the configuration as cached on the JS side, which is only
+  /// known once a call it was sent with succeeds.
+  fn mark_config_loaded(&mut self, sent_config: Option<SentConfig>) {
+    let Some(sent_config) = sent_config else {
+      return;
+    };
+    if let Some(loaded_config) = self.configs.get_mut(&sent_config.config_id)
+      && Arc::ptr_eq(&loaded_config.config, &sent_config.config)
+    {
+      loaded_config.extensions.insert(sent_config.extension);
+    }
   }
+
+  async fn call<A: Serialize, R: DeserializeOwned>(
+    &mut self,
+    fn_path: &str,
+    args: A,
+  ) -> Result<R, Error> {
+    let func = self.runtime.get_fn(fn_path)?;
+    self.runtime.call_fn(&func, args).await
+  }
+
+  async fn release_config(&mut self, config_id: Option<u32>) -> Result<(), Error> {
+    match config_id {
+      Some(config_id) => self.call("dprint.releaseConfig", (config_id,)).await,
+      None => Ok(()),
+    }
+  }
+
+  async fn call_format_texts(
+    &mut self,
+    release_config_ids: Vec<u32>,
+    all_args: Vec<serde_json::Value>,
+    on_output: impl FnMut(FormatTextsOutput) + 'static,
+  ) -> Result<(), Error> {
+    for config_id in release_config_ids {
+      self.release_config(Some(config_id)).await?;
+    }
+    let func = self.runtime.get_fn("dprint.formatTexts")?;
+    self
+      .runtime
+      .call_fn_with_callback(&func, (all_args,), on_output)
+      .await
+  }
+
+  /// Adds the parser Prettier infers for the file to the trace when tracing.
+  async fn trace_parser(&mut self, file_path: &Path) -> Result<(), Error> {
+    if !trace::is_enabled() {
+      return Ok(());
+    }
+    let _span = Span::new("infer parser");
+    let parser = self.infer_parser(file_path).await?;
+    trace::add_file_arg("parser", parser);
+    Ok(())
+  }
+
+  async fn infer_parser(&mut self, file_path: &Path) -> Result<Option<String>, Error> {
+    self
+      .call("dprint.inferParser", (file_path.to_string_lossy(),))
+      .await
+  }
+
+  /// Logs a warning when the file took longer to format than
+  /// `"slowFileWarningMs"` and it hasn't been reported before.
+  async fn warn_if_slow(&mut self, slow_file: Option<SlowFileCheck>, elapsed: Duration) {
+    let Some(slow_file) = slow_file else {
+      return;
+    };
+    if slow_file.should_report(&self.slow_files, elapsed) {
+      self.warn_slow_file(&slow_file, elapsed).await;
+    }
+  }
+
+  async fn warn_slow_file(&mut self, slow_file: &SlowFileCheck, elapsed: Duration) {
+    let parser = self
+      .infer_parser(&slow_file.file_path)
+      .await
+      .ok()
+      .flatten()
+      .unwrap_or_else(|| "unknown".to_string());
+    eprintln!(
+      "[dprint-plugin-prettier] Warning: Formatting {} ({} bytes, {} parser) took {}ms, which exceeds \"slowFileWarningMs\" of {}ms.",
+      slow_file.file_path.display(),
+      slow_file.file_size,
+      parser,
+      elapsed.as_millis(),
+      slow_file.threshold_ms,
+    );
+  }
+}
+
+/// The arguments for a call to `dprint.formatText`.
+struct FormatArgs {
+  /// A configuration to release before the call.
+  release_config_id: Option<u32>,
+  /// The configuration sent in the arguments, if it's not loaded yet.
+  sent_config: Option<SentConfig>,
+  args: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A configuration that was sent to the isolate for an extension.
+struct SentConfig {
+  config_id: FormatConfigId,
+  config: Arc<PrettierConfig>,
+  extension: String,
+}
+
+/// Information for checking if a file was slow to format.
+struct SlowFileCheck {
+  file_path: PathBuf,
+  file_size: usize,
+  threshold_ms: u32,
+}
+
+impl SlowFileCheck {
+  fn new(request: &FormatRequest<PrettierConfig>) -> Option<Self> {
+    request
+      .config
+      .slow_file_warning_ms
+      .map(|threshold_ms| SlowFileCheck {
+        file_path: request.file_path.clone(),
+        file_size: request.file_bytes.len(),
+        threshold_ms,
+      })
+  }
+
+  fn should_report(&self, slow_files: &SlowFiles, elapsed: Duration) -> bool {
+    slow_files.should_report(&self.file_path, elapsed, self.threshold_ms)
+  }
+}
+
+/// A file in a call to `dprint.formatTexts` that's waiting for its result.
+struct BatchFile {
+  /// The index of the request in the batch passed to `format_texts`.
+  index: usize,
+  slow_file: Option<SlowFileCheck>,
+  /// The parser to add to the file's span when tracing.
+  parser: Option<String>,
+  sent_config: Option<SentConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatWithCursorOutput {
+  formatted: String,
+  cursor_offset: usize,
+}
+
+/// The result of formatting one of the files passed to `dprint.formatTexts`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatTextsOutput {
+  index: usize,
+  formatted: Option<String>,
+  error: Option<String>,
+  duration_ms: u64,
 }
 
 #[async_trait(?Send)]
@@ -60,44 +321,175 @@ impl Formatter<PrettierConfig> for PrettierFormatter {
     request: FormatRequest<PrettierConfig>,
   ) -> Result<Option<Vec<u8>>, Error> {
     // todo: implement cancellation and range formatting
-    let request_value = serde_json::Value::Object({
-      let mut obj = serde_json::Map::new();
-      obj.insert(
-        "filePath".to_string(),
-        request.file_path.to_string_lossy().into(),
-      );
-      obj.insert(
-        "fileText".to_string(),
-        String::from_utf8(request.file_bytes)?.into(),
-      );
-      obj
-    });
-    let file_path = request.file_path.to_string_lossy();
-    let config = &request.config;
-    let code = format!(
-      "(async () => {{ return await dprint.formatText({{ ...{}, config: {}, pluginsConfig: {} }}); }})()",
-      request_value,
-      serde_json::to_string(&resolve_config(&file_path, config)).unwrap(),
-      serde_json::to_string(&config.plugins).unwrap(),
-    );
-    self
-      .runtime
-      .execute_format_script(code)
+    let slow_file = SlowFileCheck::new(&request);
+    let start = Instant::now();
+    self.trace_parser(&request.file_path).await?;
+    let serialize_span = Span::new("serialize");
+    let format_args = self.create_format_args(request)?;
+    drop(serialize_span);
+    self.release_config(format_args.release_config_id).await?;
+    let result = self
+      .call::<_, Option<String>>("dprint.formatText", (format_args.args,))
       .await
-      .map(|s| s.map(|s| s.into_bytes()))
+      .map(|s| s.map(|s| s.into_bytes()));
+    if result.is_ok() {
+      self.mark_config_loaded(format_args.sent_config);
+    }
+    self.warn_if_slow(slow_file, start.elapsed()).await;
+    result
+  }
+
+  async fn format_text_with_cursor(
+    &mut self,
+    request: FormatRequest<PrettierConfig>,
+    cursor_offset: usize,
+  ) -> Result<Option<FormatWithCursorResult>, Error> {
+    let file_text = std::str::from_utf8(&request.file_bytes)?;
+    let cursor_offset = utf8_to_utf16_offset(file_text, cursor_offset);
+    let slow_file = SlowFileCheck::new(&request);
+    let start = Instant::now();
+    self.trace_parser(&request.file_path).await?;
+    let serialize_span = Span::new("serialize");
+    let mut format_args = self.create_format_args(request)?;
+    format_args
+      .args
+      .insert("cursorOffset".to_string(), cursor_offset.into());
+    drop(serialize_span);
+    self.release_config(format_args.release_config_id).await?;
+    let output = self
+      .call::<_, Option<FormatWithCursorOutput>>("dprint.formatTextWithCursor", (format_args.args,))
+      .await;
+    if output.is_ok() {
+      self.mark_config_loaded(format_args.sent_config);
+    }
+    self.warn_if_slow(slow_file, start.elapsed()).await;
+    Ok(output?.map(|output| FormatWithCursorResult {
+      cursor_offset: utf16_to_utf8_offset(&output.formatted, output.cursor_offset),
+      text: output.formatted.into_bytes(),
+    }))
+  }
+
+  async fn format_texts(
+    &mut self,
+    requests: Vec<FormatRequest<PrettierConfig>>,
+    on_result: OnBatchResult,
+  ) {
+    let on_result = Rc::new(RefCell::new(on_result));
+    let mut files = Vec::with_capacity(requests.len());
+    let mut release_config_ids = Vec::new();
+    let mut all_args = Vec::with_capacity(requests.len());
+    for (index, request) in requests.into_iter().enumerate() {
+      let slow_file = SlowFileCheck::new(&request);
+      let parser = if trace::is_enabled() {
+        let _span = Span::new("infer parser");
+        self.infer_parser(&request.file_path).await
+      } else {
+        Ok(None)
+      };
+      let serialize_span = Span::new("serialize");
+      // errors creating the arguments are kept with their request
+      // and nothing is sent to the isolate for it
+      match parser.and_then(|parser| Ok((parser, self.create_format_args(request)?))) {
+        Ok((parser, format_args)) => {
+          release_config_ids.extend(format_args.release_config_id);
+          all_args.push(serde_json::Value::Object(format_args.args));
+          files.push(Some(BatchFile {
+            index,
+            slow_file,
+            parser,
+            sent_config: format_args.sent_config,
+          }));
+        }
+        Err(err) => (on_result.borrow_mut())(BatchResult {
+          index,
+          result: Err(err),
+          duration: Duration::ZERO,
+        }),
+      }
+      drop(serialize_span);
+    }
+
+    let files = Rc::new(RefCell::new(files));
+    let slow_files = Rc::new(RefCell::new(Vec::new()));
+    let loaded_configs = Rc::new(RefCell::new(Vec::new()));
+    let on_output = {
+      let files = files.clone();
+      let slow_files = slow_files.clone();
+      let loaded_configs = loaded_configs.clone();
+      let reported_slow_files = self.slow_files.clone();
+      let on_result = on_result.clone();
+      move |output: FormatTextsOutput| {
+        let Some(file) = files
+          .borrow_mut()
+          .get_mut(output.index)
+          .and_then(Option::take)
+        else {
+          return;
+        };
+        let duration = Duration::from_millis(output.duration_ms);
+        if let Some(slow_file) = file.slow_file
+          && slow_file.should_report(&reported_slow_files, duration)
+        {
+          slow_files.borrow_mut().push((slow_file, duration));
+        }
+        if let Some(parser) = file.parser {
+          trace::add_file_arg("parser", parser);
+        }
+        let result = match output.error {
+          Some(error) => Err(anyhow!("{}", error)),
+          None => {
+            loaded_configs.borrow_mut().extend(file.sent_config);
+            Ok(output.formatted.map(|text| text.into_bytes()))
+          }
+        };
+        (on_result.borrow_mut())(BatchResult {
+          index: file.index,
+          result,
+          duration,
+        });
+      }
+    };
+    let result = self
+      .call_format_texts(release_config_ids, all_args, on_output)
+      .await;
+    // files without a result get the error of the call
+    let message = match result {
+      Ok(()) => "Missing result from the batch.".to_string(),
+      Err(err) => format!("{:#}", err),
+    };
+    for file in files.borrow_mut().drain(..).flatten() {
+      (on_result.borrow_mut())(BatchResult {
+        index: file.index,
+        result: Err(anyhow!("{}", message)),
+        duration: Duration::ZERO,
+      });
+    }
+
+    for sent_config in loaded_configs.take() {
+      self.mark_config_loaded(Some(sent_config));
+    }
+    let slow_files = slow_files.take();
+    for (slow_file, elapsed) in slow_files {
+      self.warn_slow_file(&slow_file, elapsed).await;
+    }
+  }
+
+  fn heap_statistics(&mut self) -> Option<HeapStatistics> {
+    Some(self.runtime.heap_statistics())
   }
 }
 
-fn resolve_config<'a>(
-  file_path: &str,
+pub(crate) fn get_extension(file_path: &str) -> Option<String> {
+  file_path
+    .rfind('.')
+    .map(|index| file_path[index + 1..].to_lowercase())
+}
+
+pub(crate) fn resolve_config<'a>(
+  extension: &str,
   config: &'a PrettierConfig,
 ) -> Cow<'a, serde_json::Map<String, serde_json::Value>> {
-  let ext = if let Some(index) = file_path.rfind('.') {
-    file_path[index + 1..].to_lowercase()
-  } else {
-    return Cow::Borrowed(&config.main);
-  };
-  if let Some(override_config) = config.extension_overrides.get(&ext) {
+  if let Some(override_config) = config.extension_overrides.get(extension) {
     let mut new_config = config.main.clone();
     for (key, value) in override_config.as_object().unwrap().iter() {
       new_config.insert(key.to_string(), value.clone());
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+/// Only the start of a file is searched for the generated file markers.
+const MARKER_SEARCH_BYTES: usize = 1024;
+/// Files smaller than this are never considered minified.
+const MIN_MINIFIED_FILE_BYTES: usize = 1024;
+/// Files with an average line length above this are considered minified.
+const MAX_AVG_LINE_LENGTH: usize = 300;
+
+/// Gets if the file is generated or minified, which Prettier would take a
+/// long time to format and produce a large diff for.
+pub fn is_generated_or_minified(file_path: &Path, file_bytes: &[u8], markers: &[String]) -> bool {
+  is_minified_file_name(file_path)
+    || has_generated_marker(file_bytes, markers)
+    || is_minified_text(file_bytes)
+}
+
+fn is_minified_file_name(file_path: &Path) -> bool {
+  file_path
+    .file_stem()
+    .map(|stem| stem.to_string_lossy().to_lowercase())
+    .is_some_and(|stem| stem.ends_with(".min") || stem.ends_with("-min"))
+}
+
+fn has_generated_marker(file_bytes: &[u8], markers: &[String]) -> bool {
+  let start = &file_bytes[..file_bytes.len().min(MARKER_SEARCH_BYTES)];
+  let start = String::from_utf8_lossy(start);
+  markers.iter().any(|marker| start.contains(marker.as_str()))
+}
+
+fn is_minified_text(file_bytes: &[u8]) -> bool {
+  if file_bytes.len() < MIN_MINIFIED_FILE_BYTES {
+    return false;
+  }
+  let line_count = file_bytes.iter().filter(|b| **b == b'\n').count() + 1;
+  file_bytes.len() / line_count > MAX_AVG_LINE_LENGTH
+}
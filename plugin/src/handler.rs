@@ -1,6 +1,8 @@
 use std::sync::Arc;
 use std::sync::OnceLock;
 
+use deno_core::anyhow::bail;
+use deno_core::anyhow::Error;
 use dprint_core::async_runtime::async_trait;
 use dprint_core::async_runtime::LocalBoxFuture;
 use dprint_core::configuration::ConfigKeyMap;
@@ -12,12 +14,20 @@ use dprint_core::plugins::FormatResult;
 use dprint_core::plugins::HostFormatRequest;
 use dprint_core::plugins::PluginInfo;
 use dprint_core::plugins::PluginResolveConfigurationResult;
-use dprint_plugin_deno_base::channel::Channel;
-use dprint_plugin_deno_base::channel::CreateChannelOptions;
+use dprint_plugin_deno_base::channel::FormatWithCursorResponse;
+use dprint_plugin_deno_base::plugin::JsPluginHandler;
+use dprint_plugin_deno_base::plugin::JsPluginHandlerBuilder;
+use dprint_plugin_deno_base::trace::Tracer;
 
+use crate::bundled_plugins::get_bundled_plugins;
+use crate::cache::ResultCache;
 use crate::config::resolve_config;
+use crate::config::MaxFileSizeBehavior;
 use crate::config::PrettierConfig;
 use crate::formatter::PrettierFormatter;
+use crate::generated::is_generated_or_minified;
+use crate::slow_files::SlowFiles;
+use crate::versions::get_versions_text;
 
 fn get_supported_extensions() -> &'static Vec<String> {
   static SUPPORTED_EXTENSIONS: OnceLock<Vec<String>> = OnceLock::new();
@@ -28,40 +38,99 @@ fn get_supported_extensions() -> &'static Vec<String> {
   })
 }
 
+/// Gets the tracer that records format spans to the file at the
+/// path in the DPRINT_PRETTIER_TRACE environment variable.
+pub fn get_tracer() -> Option<&'static Arc<Tracer>> {
+  static TRACER: OnceLock<Option<Arc<Tracer>>> = OnceLock::new();
+  TRACER
+    .get_or_init(|| match Tracer::from_env("DPRINT_PRETTIER_TRACE") {
+      Ok(tracer) => tracer.map(Arc::new),
+      Err(err) => {
+        eprintln!("[dprint-plugin-prettier] Warning: {:#}", err);
+        None
+      }
+    })
+    .as_ref()
+}
+
 pub struct PrettierPluginHandler {
-  channel: Arc<Channel<PrettierConfig>>,
+  inner: JsPluginHandler<PrettierConfig>,
 }
 
 impl Default for PrettierPluginHandler {
   fn default() -> Self {
+    let slow_files = Arc::new(SlowFiles::default());
+    let plugin_info = PluginInfo {
+      name: env!("CARGO_PKG_NAME").to_string(),
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      config_key: "prettier".to_string(),
+      help_url: "https://dprint.dev/plugins/prettier".to_string(),
+      config_schema_url: "".to_string(),
+      update_url: Some(
+        "https://plugins.dprint.dev/dprint/dprint-plugin-prettier/latest.json".to_string(),
+      ),
+    };
+    let license_text = format!(
+      "{}\n\nEmbedded packages:\n\n{}\nThird party licenses:\n\n{}",
+      include_str!("../../LICENSE").trim_end(),
+      get_versions_text(),
+      include_str!(concat!(env!("OUT_DIR"), "/THIRD_PARTY_LICENSES.txt")),
+    );
     Self {
-      channel: Arc::new(Channel::new(CreateChannelOptions {
-        avg_isolate_memory_usage: 600_000, // 600MB guess
-        create_formatter_cb: Arc::new(|| Box::<PrettierFormatter>::default()),
-      })),
+      inner: JsPluginHandlerBuilder::new(plugin_info, resolve_plugin_config)
+        .license_text(license_text)
+        // a custom formatter rather than the snapshot's standard calling convention
+        // so each isolate can cache the resolved configuration per extension
+        .formatter(move || {
+          Ok(Box::new(PrettierFormatter::with_slow_files(
+            slow_files.clone(),
+          )?))
+        })
+        // Each isolate's V8 heap is capped at 512MB (see `STARTUP_SNAPSHOT` in formatter.rs), so
+        // this is the cap plus ~88MB for memory outside the heap like compiled code.
+        // Keep it above the largest "isolate memory" from tests/memory.rs.
+        .avg_isolate_memory_usage(600 * 1024 * 1024)
+        // An allowance for the AST, doc tree, and output Prettier creates for each
+        // byte of a file. Keep it above the largest "per file byte" from tests/memory.rs.
+        .memory_per_file_byte(50)
+        .tracer(get_tracer().cloned())
+        .build(),
     }
   }
 }
 
+impl PrettierPluginHandler {
+  /// Formats the file and maps the cursor byte offset to its position in the
+  /// formatted text so editors can preserve the cursor on format.
+  ///
+  /// Returns `None` when the text doesn't change.
+  pub async fn format_with_cursor(
+    &self,
+    request: FormatRequest<PrettierConfig>,
+    cursor_offset: usize,
+  ) -> FormatWithCursorResponse {
+    if request.range.is_some() {
+      // no support for range formatting
+      return Ok(None);
+    }
+    if exceeds_max_file_size(&request)? || is_skipped_generated_file(&request) {
+      return Ok(None);
+    }
+
+    self.inner.format_with_cursor(request, cursor_offset).await
+  }
+}
+
 #[async_trait(?Send)]
 impl AsyncPluginHandler for PrettierPluginHandler {
   type Configuration = PrettierConfig;
 
   fn plugin_info(&self) -> PluginInfo {
-    PluginInfo {
-      name: env!("CARGO_PKG_NAME").to_string(),
-      version: env!("CARGO_PKG_VERSION").to_string(),
-      config_key: "prettier".to_string(),
-      help_url: "https://dprint.dev/plugins/prettier".to_string(),
-      config_schema_url: "".to_string(),
-      update_url: Some(
-        "https://plugins.dprint.dev/dprint/dprint-plugin-prettier/latest.json".to_string(),
-      ),
-    }
+    self.inner.plugin_info()
   }
 
   fn license_text(&self) -> String {
-    include_str!("../../LICENSE").to_string()
+    self.inner.license_text()
   }
 
   async fn resolve_config(
@@ -69,27 +138,93 @@ impl AsyncPluginHandler for PrettierPluginHandler {
     config: ConfigKeyMap,
     global_config: GlobalConfiguration,
   ) -> PluginResolveConfigurationResult<Self::Configuration> {
-    let result = resolve_config(config, global_config);
-    PluginResolveConfigurationResult {
-      config: result.config,
-      diagnostics: result.diagnostics,
-      file_matching: FileMatchingInfo {
-        file_extensions: get_supported_extensions().clone(),
-        file_names: vec![],
-      },
-    }
+    self.inner.resolve_config(config, global_config).await
   }
 
   async fn format(
     &self,
     request: FormatRequest<Self::Configuration>,
-    _format_with_host: impl FnMut(HostFormatRequest) -> LocalBoxFuture<'static, FormatResult> + 'static,
+    format_with_host: impl FnMut(HostFormatRequest) -> LocalBoxFuture<'static, FormatResult> + 'static,
   ) -> FormatResult {
     if request.range.is_some() {
       // no support for range formatting
       return Ok(None);
     }
+    if exceeds_max_file_size(&request)? || is_skipped_generated_file(&request) {
+      return Ok(None);
+    }
 
-    self.channel.format(request).await
+    let Some(cache_dir) = request.config.cache_dir.clone() else {
+      return self.inner.format(request, format_with_host).await;
+    };
+    let cache = ResultCache::new(cache_dir);
+    let key = cache.key(&request.file_path, &request.file_bytes, &request.config);
+    if let Some(result) = cache.get(&key) {
+      return Ok(result);
+    }
+    let result = self.inner.format(request, format_with_host).await?;
+    cache.set(&key, result.as_deref());
+    Ok(result)
   }
 }
+
+fn resolve_plugin_config(
+  config: ConfigKeyMap,
+  global_config: GlobalConfiguration,
+) -> PluginResolveConfigurationResult<PrettierConfig> {
+  let result = resolve_config(config, global_config);
+  let mut file_extensions = get_supported_extensions().clone();
+  for plugin in get_bundled_plugins() {
+    if result.config.plugins.is_enabled(&plugin.name) {
+      file_extensions.extend(plugin.extensions.iter().cloned());
+    }
+  }
+  PluginResolveConfigurationResult {
+    config: result.config,
+    diagnostics: result.diagnostics,
+    file_matching: FileMatchingInfo {
+      file_extensions,
+      file_names: vec![],
+    },
+  }
+}
+
+/// Checks the file against `"maxFileSize"`, which prevents formatting large
+/// files that could exceed the memory limit of the isolate.
+///
+/// Returns `true` when the file should be skipped.
+fn exceeds_max_file_size(request: &FormatRequest<PrettierConfig>) -> Result<bool, Error> {
+  let Some(max_file_size) = request.config.max_file_size else {
+    return Ok(false);
+  };
+  let file_size = request.file_bytes.len();
+  if file_size <= max_file_size {
+    return Ok(false);
+  }
+  match request.config.max_file_size_behavior {
+    MaxFileSizeBehavior::Skip => {
+      eprintln!(
+        "[dprint-plugin-prettier] Warning: Skipped {} because its size of {} bytes exceeds \"maxFileSize\" of {} bytes.",
+        request.file_path.display(),
+        file_size,
+        max_file_size,
+      );
+      Ok(true)
+    }
+    MaxFileSizeBehavior::Error => bail!(
+      "File size of {} bytes exceeds \"maxFileSize\" of {} bytes.",
+      file_size,
+      max_file_size
+    ),
+  }
+}
+
+fn is_skipped_generated_file(request: &FormatRequest<PrettierConfig>) -> bool {
+  let config = &request.config;
+  config.skip_generated_files
+    && is_generated_or_minified(
+      &request.file_path,
+      &request.file_bytes,
+      &config.generated_file_markers,
+    )
+}
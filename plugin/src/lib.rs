@@ -1,7 +1,23 @@
 extern crate dprint_core;
 
+mod api;
+mod bundled_plugins;
+mod cache;
 pub mod config;
 mod formatter;
+mod generated;
 mod handler;
+mod slow_files;
+pub mod versions;
 
+pub use api::format_text;
+pub use api::Prettier;
 pub use handler::*;
+
+/// Internals used by the integration tests, which aren't part of the public API.
+#[doc(hidden)]
+pub mod testing {
+  pub use crate::formatter::get_extension_parsers;
+  pub use crate::formatter::PrettierFormatter;
+  pub use crate::slow_files::SlowFiles;
+}
@@ -3,15 +3,34 @@ use dprint_core::plugins::process::handle_process_stdio_messages;
 use dprint_core::plugins::process::start_parent_process_checker_task;
 use dprint_plugin_deno_base::runtime::JsRuntime;
 use dprint_plugin_deno_base::util::create_tokio_runtime;
-use handler::PrettierPluginHandler;
+use dprint_plugin_prettier::get_tracer;
+use dprint_plugin_prettier::PrettierPluginHandler;
 
-mod config;
-mod formatter;
-mod handler;
+mod cli;
 
 fn main() {
   JsRuntime::initialize_main_thread();
   let runtime = create_tokio_runtime();
+
+  let args = std::env::args().skip(1).collect::<Vec<_>>();
+  match cli::parse_cli_args(&args) {
+    Ok(Some(cli_args)) => {
+      let exit_code = runtime
+        .block_on(cli::run_cli(cli_args))
+        .unwrap_or_else(|err| {
+          eprintln!("{:#}", err);
+          1
+        });
+      print_trace_summary();
+      std::process::exit(exit_code);
+    }
+    Ok(None) => {}
+    Err(err) => {
+      eprintln!("{:#}", err);
+      std::process::exit(1);
+    }
+  }
+
   let result = runtime.block_on(async {
     if let Some(parent_process_id) = get_parent_process_id_from_cli_args() {
       start_parent_process_checker_task(parent_process_id);
@@ -20,8 +39,16 @@ fn main() {
     handle_process_stdio_messages(PrettierPluginHandler::default()).await
   });
 
+  print_trace_summary();
+
   if let Err(err) = result {
     eprintln!("Shutting down due to error: {}", err);
     std::process::exit(1);
   }
 }
+
+fn print_trace_summary() {
+  if let Some(tracer) = get_tracer() {
+    eprint!("{}", tracer.slowest_files_text(10));
+  }
+}
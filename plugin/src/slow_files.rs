@@ -0,0 +1,28 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use deno_core::parking_lot::Mutex;
+
+/// Files that took longer than `"slowFileWarningMs"` to format. This is
+/// shared by all the isolates so a file is only reported once, which
+/// matters in editor sessions where the same file is formatted repeatedly.
+#[derive(Default)]
+pub struct SlowFiles {
+  reported: Mutex<HashSet<PathBuf>>,
+}
+
+impl SlowFiles {
+  /// Gets if formatting the file took long enough to report and
+  /// marks it as reported.
+  pub fn should_report(&self, file_path: &Path, elapsed: Duration, threshold_ms: u32) -> bool {
+    elapsed.as_millis() >= threshold_ms as u128
+      && self.reported.lock().insert(file_path.to_path_buf())
+  }
+
+  /// Gets if the file has been reported as slow.
+  pub fn is_reported(&self, file_path: &Path) -> bool {
+    self.reported.lock().contains(file_path)
+  }
+}
@@ -0,0 +1,30 @@
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// An npm package embedded in the snapshot.
+#[derive(Deserialize)]
+pub struct EmbeddedPackage {
+  pub name: String,
+  pub version: String,
+}
+
+/// Gets the versions of Prettier and the bundled Prettier plugins
+/// that were captured at build time.
+pub fn get_embedded_packages() -> &'static Vec<EmbeddedPackage> {
+  static EMBEDDED_PACKAGES: OnceLock<Vec<EmbeddedPackage>> = OnceLock::new();
+  EMBEDDED_PACKAGES.get_or_init(|| {
+    let json_bytes = include_bytes!(concat!(env!("OUT_DIR"), "/EMBEDDED_PACKAGES.json"));
+
+    deno_core::serde_json::from_slice(json_bytes).unwrap()
+  })
+}
+
+/// Text describing the embedded package versions with one package per line.
+pub fn get_versions_text() -> String {
+  let mut text = format!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+  for package in get_embedded_packages() {
+    text.push_str(&format!("{} {}\n", package.name, package.version));
+  }
+  text
+}
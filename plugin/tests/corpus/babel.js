@@ -0,0 +1,29 @@
+import { readFile } from "fs/promises"
+import * as path from 'path'
+
+export default class Loader extends Base {
+  #cache = new Map()
+  static instances = 0
+
+  constructor(root, { encoding = 'utf8', ...options } = {}) {
+    super(options); this.root = root
+    this.encoding = encoding
+    Loader.instances++
+  }
+
+  async load(name) {
+    if (this.#cache.has(name)) return this.#cache.get(name)
+    const text = await readFile(path.join(this.root, name), this.encoding)
+    const value = { name, text, lines: text.split("\n").map((line, index) => ({ index, line, length: line.length })) }
+    this.#cache.set(name, value)
+    return value
+  }
+}
+
+const element = <div className="app">{items.map(item => <Item key={item.id} {...item} />)}</div>
+
+export function* range(start, end, step = 1) { for (let i = start; i < end; i += step) yield i }
+
+label: for (const value of range(0, 10)) {
+  switch (value) { case 1: case 2: continue label; default: console.log(value ?? "none", value?.toString()) }
+}
@@ -0,0 +1,25 @@
+import type { Readable } from "stream"
+export enum Kind { Text = 1, Binary, Unknown = "unknown" }
+
+export interface Entry<T extends object = {}> { readonly kind: Kind; value?: T; children: Entry<T>[] }
+
+type Mapped<T> = { [K in keyof T as `get${Capitalize<string & K>}`]: () => T[K] }
+
+export abstract class Store<TValue> implements Iterable<TValue> {
+  private readonly items: TValue[] = []
+  constructor(protected readonly name: string, public limit?: number) {}
+
+  abstract validate(value: unknown): value is TValue
+
+  @logged()
+  add(...values: TValue[]): this { this.items.push(...values.filter(v => this.validate(v))); return this }
+
+  *[Symbol.iterator]() { yield* this.items }
+}
+
+namespace Utils { export const identity = <T,>(value: T): T => value }
+
+declare module "example" { export function create(stream: Readable): Promise<void> }
+
+const value = ["a", "b"] as const satisfies readonly string[]
+let nonNull = maybe!.value as unknown as Entry
@@ -0,0 +1,160 @@
+//! Property tests for the boundary where configuration and file text
+//! are passed to the JS isolate.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use deno_core::anyhow::Error;
+use dprint_core::configuration::ConfigKeyMap;
+use dprint_core::configuration::ConfigKeyValue;
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use dprint_plugin_deno_base::channel::Formatter;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use dprint_plugin_prettier::config::resolve_config;
+use dprint_plugin_prettier::config::PrettierConfig;
+use dprint_plugin_prettier::testing::PrettierFormatter;
+use proptest::prelude::*;
+use proptest::test_runner::Config;
+use proptest::test_runner::TestRunner;
+
+/// Strings that would change how a canary file is formatted if they were
+/// evaluated as code or ended up on `Object.prototype`.
+const PAYLOADS: &[&str] = &[
+  "\"}); Object.prototype.semi = false; ({\"",
+  "'); Object.prototype.singleQuote = true; ('",
+  "`${globalThis.dprint = undefined}`",
+  "</script><script>globalThis.dprint = undefined</script>",
+  "\u{2028}\u{2029}",
+  "\\u0000\\",
+  "constructor",
+  "__proto__",
+];
+
+fn string_strategy() -> impl Strategy<Value = String> {
+  prop_oneof![
+    proptest::sample::select(PAYLOADS).prop_map(|value| value.to_string()),
+    any::<String>(),
+  ]
+}
+
+fn key_strategy() -> impl Strategy<Value = String> {
+  prop_oneof![
+    proptest::sample::select(
+      &[
+        "semi",
+        "singleQuote",
+        "printWidth",
+        "tabWidth",
+        "parser",
+        "plugins",
+        "overrides",
+        "__proto__",
+        "constructor",
+        "prototype",
+        "ts.semi",
+        "__proto__.semi",
+        "js.__proto__",
+        "plugin.svelte",
+        "plugin.__proto__",
+      ][..]
+    )
+    .prop_map(|key| key.to_string()),
+    string_strategy(),
+  ]
+}
+
+fn value_strategy() -> impl Strategy<Value = ConfigKeyValue> {
+  let leaf = prop_oneof![
+    any::<bool>().prop_map(ConfigKeyValue::Bool),
+    any::<i32>().prop_map(ConfigKeyValue::Number),
+    string_strategy().prop_map(ConfigKeyValue::String),
+    Just(ConfigKeyValue::Null),
+  ];
+  leaf.prop_recursive(3, 16, 4, |inner| {
+    prop_oneof![
+      proptest::collection::vec(inner.clone(), 0..4).prop_map(ConfigKeyValue::Array),
+      proptest::collection::vec((key_strategy(), inner), 0..4)
+        .prop_map(|entries| ConfigKeyValue::Object(entries.into_iter().collect())),
+    ]
+  })
+}
+
+fn config_strategy() -> impl Strategy<Value = ConfigKeyMap> {
+  proptest::collection::vec((key_strategy(), value_strategy()), 0..6)
+    .prop_map(|entries| entries.into_iter().collect())
+}
+
+fn file_strategy() -> impl Strategy<Value = (String, String)> {
+  (
+    proptest::sample::select(
+      &[
+        "file.ts",
+        "file.css",
+        "file.json",
+        "file.md",
+        "file.__proto__",
+      ][..],
+    )
+    .prop_map(|file_name| file_name.to_string()),
+    string_strategy(),
+  )
+}
+
+#[test]
+fn config_boundary() {
+  let runtime = create_tokio_runtime();
+  let formatter = RefCell::new(PrettierFormatter::new().unwrap());
+  let default_config = Arc::new(PrettierConfig::default());
+
+  let mut runner = TestRunner::new(Config::with_cases(128));
+  runner
+    .run(
+      &(config_strategy(), file_strategy()),
+      |(config, (file_name, file_text))| {
+        let config = Arc::new(resolve_config(config, Default::default()).config);
+        let mut formatter = formatter.borrow_mut();
+        runtime.block_on(async {
+          // succeeding or erroring is fine, but it shouldn't panic
+          let _ = format(&mut formatter, 1, &config, &file_name, &file_text).await;
+
+          let canary = format(
+            &mut formatter,
+            2,
+            &default_config,
+            "canary.ts",
+            "const a = 'b'",
+          )
+          .await;
+          prop_assert_eq!(
+            canary.map_err(|err| err.to_string()),
+            Ok(Some("const a = \"b\";\n".to_string()))
+          );
+          Ok(())
+        })
+      },
+    )
+    .unwrap();
+}
+
+async fn format(
+  formatter: &mut PrettierFormatter,
+  config_id: u32,
+  config: &Arc<PrettierConfig>,
+  file_name: &str,
+  file_text: &str,
+) -> Result<Option<String>, Error> {
+  let result = formatter
+    .format_text(FormatRequest {
+      config_id: FormatConfigId::from_raw(config_id),
+      file_path: PathBuf::from(file_name),
+      file_bytes: file_text.to_string().into_bytes(),
+      config: config.clone(),
+      range: None,
+      token: Arc::new(NullCancellationToken),
+    })
+    .await?;
+  Ok(result.map(|bytes| String::from_utf8(bytes).unwrap()))
+}
@@ -0,0 +1,217 @@
+//! Measures the memory used by an isolate when formatting a generated corpus
+//! of each language, which is what `avg_isolate_memory_usage` is based on.
+//!
+//! Each language is formatted in a fresh isolate in its own process so memory
+//! from other languages doesn't affect its numbers. The "startup" workload
+//! formats nothing, which is the baseline before any language plugins are
+//! loaded. Comparing it with the CSS-only and YAML-only workloads shows what
+//! loading a single language costs.
+//!
+//! Run with:
+//!
+//! cargo test --release -p dprint-plugin-prettier --test memory -- --ignored --nocapture --test-threads=1
+//!
+//! Or measure a single workload (ex. `css` or `yaml`) with:
+//!
+//! DPRINT_MEMORY_LANGUAGE=css cargo test --release -p dprint-plugin-prettier --test memory -- --ignored --nocapture --exact measure_language
+
+use std::process::Command;
+use std::sync::Arc;
+
+use dprint_core::plugins::FormatConfigId;
+use dprint_core::plugins::FormatRequest;
+use dprint_core::plugins::NullCancellationToken;
+use dprint_plugin_deno_base::channel::CreateFormatterCb;
+use dprint_plugin_deno_base::memory::measure_memory;
+use dprint_plugin_deno_base::util::create_tokio_runtime;
+use dprint_plugin_prettier::config::PrettierConfig;
+use dprint_plugin_prettier::testing::PrettierFormatter;
+
+const FILES_PER_LANGUAGE: usize = 20;
+const LINES_PER_FILE: usize = 2_000;
+/// Set for the child process that measures a single language.
+const LANGUAGE_ENV_VAR: &str = "DPRINT_MEMORY_LANGUAGE";
+/// The workload that only creates the isolate.
+const STARTUP_WORKLOAD: &str = "startup";
+/// Printed by the child process before the isolate memory in bytes.
+const ISOLATE_MEMORY_PREFIX: &str = "isolate memory bytes: ";
+/// Printed by the child process before the average file size in bytes.
+const FILE_BYTES_PREFIX: &str = "average file bytes: ";
+
+type CreateLine = fn(usize) -> String;
+
+#[test]
+#[ignore]
+fn measure_isolate_memory() {
+  let mut results = Vec::new();
+  let workloads = std::iter::once(STARTUP_WORKLOAD)
+    .chain(languages().into_iter().map(|(extension, _)| extension));
+  for extension in workloads {
+    let output = Command::new(std::env::current_exe().unwrap())
+      .args([
+        "measure_language",
+        "--exact",
+        "--ignored",
+        "--nocapture",
+        "--test-threads=1",
+      ])
+      .env(LANGUAGE_ENV_VAR, extension)
+      .output()
+      .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+      output.status.success(),
+      "Measuring {} failed.\n{}{}",
+      extension,
+      stdout,
+      String::from_utf8_lossy(&output.stderr)
+    );
+    let get_value = |prefix: &str| {
+      stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(prefix))
+        .and_then(|bytes| bytes.parse::<u64>().ok())
+        .unwrap()
+    };
+    results.push((
+      extension,
+      get_value(ISOLATE_MEMORY_PREFIX),
+      get_value(FILE_BYTES_PREFIX),
+    ));
+  }
+
+  // the memory used above the startup workload for each byte of a file
+  // is what `memory_per_file_byte` is based on
+  let startup_memory = results[0].1;
+  println!("\nIsolate memory per workload:");
+  for (extension, isolate_memory, file_bytes) in &results {
+    print!("  {:<8} {}", extension, format_mb(*isolate_memory));
+    if *file_bytes > 0 {
+      print!(
+        ", {} per file byte",
+        isolate_memory.saturating_sub(startup_memory) / file_bytes
+      );
+    }
+    println!();
+  }
+  let (extension, max_isolate_memory, _) = results
+    .iter()
+    .max_by_key(|(_, isolate_memory, _)| *isolate_memory)
+    .unwrap();
+  println!(
+    "\nLargest isolate memory usage: {} ({})",
+    format_mb(*max_isolate_memory),
+    extension
+  );
+}
+
+/// Measures the workload in `DPRINT_MEMORY_LANGUAGE`. This is run in a
+/// separate process for each workload by `measure_isolate_memory`.
+#[test]
+#[ignore]
+fn measure_language() {
+  let Ok(language) = std::env::var(LANGUAGE_ENV_VAR) else {
+    return;
+  };
+  let runtime = create_tokio_runtime();
+  let create_formatter_cb: Arc<CreateFormatterCb<PrettierConfig>> =
+    Arc::new(|| Ok(Box::new(PrettierFormatter::new()?)));
+  let config = Arc::new(PrettierConfig::default());
+  let requests = if language == STARTUP_WORKLOAD {
+    Vec::new()
+  } else {
+    let (extension, create_line) = languages()
+      .into_iter()
+      .find(|(extension, _)| *extension == language)
+      .unwrap();
+    (0..FILES_PER_LANGUAGE)
+      .map(|file_index| FormatRequest {
+        file_path: format!("file{}.{}", file_index, extension).into(),
+        file_bytes: (0..LINES_PER_FILE)
+          .map(|line_index| create_line(file_index * LINES_PER_FILE + line_index))
+          .collect::<String>()
+          .into_bytes(),
+        config_id: FormatConfigId::from_raw(1),
+        config: config.clone(),
+        range: None,
+        token: Arc::new(NullCancellationToken),
+      })
+      .collect::<Vec<_>>()
+  };
+  let report = runtime
+    .block_on(measure_memory(language, &*create_formatter_cb, requests))
+    .unwrap();
+  println!("{}", report);
+  println!("{}{}", ISOLATE_MEMORY_PREFIX, report.isolate_memory());
+  println!(
+    "{}{}",
+    FILE_BYTES_PREFIX,
+    report
+      .total_file_bytes
+      .checked_div(report.file_count)
+      .unwrap_or(0)
+  );
+}
+
+fn format_mb(bytes: u64) -> String {
+  format!("{:.1}MB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+fn languages() -> Vec<(&'static str, CreateLine)> {
+  vec![
+    ("js", |i| {
+      format!("const value{i} = {{ a: {i}, b: [\"{i}\", {i}] }};\n")
+    }),
+    ("ts", |i| {
+      format!("function fn{i}(a: number, b: string): [number, string] {{ return [a + {i}, b]; }}\n")
+    }),
+    ("css", |i| {
+      format!(
+        ".class{i} > a {{ color: #{:06x}; margin: {i}px }}\n",
+        i % 0xffffff
+      )
+    }),
+    ("scss", |i| {
+      format!("$var{i}: {i}px; .class{i} {{ a {{ margin: $var{i} }} }}\n")
+    }),
+    ("less", |i| {
+      format!("@var{i}: {i}px; .class{i} {{ a {{ margin: @var{i} }} }}\n")
+    }),
+    ("json", |i| {
+      if i % LINES_PER_FILE == 0 {
+        format!("[{{ \"key\": {i} }}\n")
+      } else if i % LINES_PER_FILE == LINES_PER_FILE - 1 {
+        format!(",{{ \"key\": {i} }}]\n")
+      } else {
+        format!(",{{ \"key\": {i}, \"values\": [{i}, \"{i}\"] }}\n")
+      }
+    }),
+    ("yaml", |i| {
+      format!("key{i}:\n  - {i}\n  - value: \"{i}\"\n")
+    }),
+    ("md", |i| {
+      format!("# Heading {i}\n\nSome *text* with `code` {i}.\n\n")
+    }),
+    ("graphql", |i| {
+      format!("query Query{i} {{ field{i}(arg: {i}) {{ id name }} }}\n")
+    }),
+    ("html", |i| {
+      format!("<div class=\"c{i}\"><span>{i}</span><a href=\"#{i}\">link</a></div>\n")
+    }),
+    ("vue", |i| {
+      if i % LINES_PER_FILE == 0 {
+        "<template>\n<div>\n".to_string()
+      } else if i % LINES_PER_FILE == LINES_PER_FILE - 1 {
+        "</div>\n</template>\n".to_string()
+      } else {
+        format!("<span :title=\"value{i}\">{{{{ value{i} }}}}</span>\n")
+      }
+    }),
+    ("hbs", |i| {
+      format!("{{{{#if value{i}}}}}<span>{{{{value{i}}}}}</span>{{{{/if}}}}\n")
+    }),
+    ("svelte", |i| {
+      format!("{{#if value{i}}}<span>{{value{i}}}</span>{{/if}}\n")
+    }),
+  ]
+}
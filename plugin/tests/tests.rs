@@ -1,6 +1,11 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 
+use deno_core::anyhow::Error;
 use deno_core::futures::FutureExt;
 use dprint_core::configuration::ConfigKeyMap;
 use dprint_core::plugins::AsyncPluginHandler;
@@ -8,8 +13,17 @@ use dprint_core::plugins::FormatConfigId;
 use dprint_core::plugins::FormatRequest;
 use dprint_core::plugins::NullCancellationToken;
 use dprint_development::*;
+use dprint_plugin_deno_base::channel::BatchResult;
+use dprint_plugin_deno_base::channel::Formatter;
 use dprint_plugin_deno_base::util::create_tokio_runtime;
 use dprint_plugin_prettier::config::resolve_config;
+use dprint_plugin_prettier::config::PrettierConfig;
+use dprint_plugin_prettier::config::PrettierConfigBuilder;
+use dprint_plugin_prettier::format_text;
+use dprint_plugin_prettier::testing::get_extension_parsers;
+use dprint_plugin_prettier::testing::PrettierFormatter;
+use dprint_plugin_prettier::testing::SlowFiles;
+use dprint_plugin_prettier::Prettier;
 use dprint_plugin_prettier::PrettierPluginHandler;
 
 use pretty_assertions::assert_eq;
@@ -110,3 +124,518 @@ fn handle_invalid_file() {
     assert_eq!(&err.to_string()[..expected.len()], expected);
   });
 }
+
+#[test]
+fn handle_unknown_plugin() {
+  let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+    "plugin.jsDoc": true,
+    "plugin.unknown": true,
+  }))
+  .unwrap();
+  let result = resolve_config(config, Default::default());
+  assert_eq!(result.diagnostics.len(), 1);
+  assert_eq!(result.diagnostics[0].property_name, "plugin.unknown");
+  assert!(result.config.plugins.is_enabled("jsDoc"));
+  assert!(result.config.plugins.is_enabled("svelte"));
+}
+
+#[test]
+fn resolve_slow_file_warning_ms() {
+  let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+    "slowFileWarningMs": 500,
+  }))
+  .unwrap();
+  let result = resolve_config(config, Default::default());
+  assert!(result.diagnostics.is_empty());
+  assert_eq!(result.config.slow_file_warning_ms, Some(500));
+  assert!(!result.config.main.contains_key("slowFileWarningMs"));
+}
+
+#[test]
+fn max_file_size() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    for (behavior, expected_error) in [
+      ("skip", None),
+      (
+        "error",
+        Some("File size of 14 bytes exceeds \"maxFileSize\" of 10 bytes."),
+      ),
+    ] {
+      let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+        "maxFileSize": 10,
+        "maxFileSizeBehavior": behavior,
+      }))
+      .unwrap();
+      let config_result = resolve_config(config, Default::default());
+      ensure_no_diagnostics(&config_result.diagnostics);
+      let result = handler
+        .format(
+          create_request("file.ts", "const   v =  5", Arc::new(config_result.config)),
+          |_| std::future::ready(Ok(None)).boxed_local(),
+        )
+        .await;
+      match expected_error {
+        Some(expected_error) => assert_eq!(result.err().unwrap().to_string(), expected_error),
+        None => assert!(result.unwrap().is_none()),
+      }
+    }
+  });
+}
+
+#[test]
+fn skip_generated_files() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+      "skipGeneratedFiles": true,
+      "generatedFileMarkers": ["@generated", "DO NOT EDIT"],
+    }))
+    .unwrap();
+    let config_result = resolve_config(config, Default::default());
+    ensure_no_diagnostics(&config_result.diagnostics);
+    let config = Arc::new(config_result.config);
+    let minified_text = "const a=1;".repeat(200);
+    let cases = [
+      ("file.ts", "// @generated\nconst   v =  5", true),
+      ("file.ts", "// DO NOT EDIT\nconst   v =  5", true),
+      ("file.min.js", "const   v =  5", true),
+      ("file.js", minified_text.as_str(), true),
+      ("file.ts", "const   v =  5", false),
+    ];
+    for (file_name, file_text, is_skipped) in cases {
+      let result = handler
+        .format(create_request(file_name, file_text, config.clone()), |_| {
+          std::future::ready(Ok(None)).boxed_local()
+        })
+        .await
+        .unwrap();
+      assert_eq!(result.is_none(), is_skipped, "{}", file_text);
+    }
+  });
+}
+
+#[test]
+fn cache_dir() {
+  let runtime = create_tokio_runtime();
+  let cache_dir = std::env::temp_dir().join(format!(
+    "dprint-plugin-prettier-cache-{}",
+    std::process::id()
+  ));
+
+  runtime.block_on(async {
+    let handler = PrettierPluginHandler::default();
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+      "cacheDir": cache_dir.to_string_lossy(),
+    }))
+    .unwrap();
+    let config_result = resolve_config(config, Default::default());
+    ensure_no_diagnostics(&config_result.diagnostics);
+    let config = Arc::new(config_result.config);
+    let format = |file_text: &str, config: &Arc<PrettierConfig>| {
+      handler.format(create_request("file.ts", file_text, config.clone()), |_| {
+        std::future::ready(Ok(None)).boxed_local()
+      })
+    };
+
+    let result = format("const   v =  5", &config).await.unwrap();
+    assert_eq!(result, Some(b"const v = 5;\n".to_vec()));
+
+    // change the cached result to show it's used instead of formatting
+    let entries = std::fs::read_dir(&cache_dir)
+      .unwrap()
+      .flat_map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap())
+      .map(|entry| entry.unwrap().path())
+      .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    std::fs::write(&entries[0], b"\x01cached").unwrap();
+    let result = format("const   v =  5", &config).await.unwrap();
+    assert_eq!(result, Some(b"cached".to_vec()));
+
+    // the enabled plugins may change how the file is parsed, so they're part of the key
+    let mut plugin_config = (*config).clone();
+    plugin_config.plugins.enabled.push("jsDoc".to_string());
+    let result = format("const   v =  5", &Arc::new(plugin_config))
+      .await
+      .unwrap();
+    assert_eq!(result, Some(b"const v = 5;\n".to_vec()));
+  });
+
+  let _ = std::fs::remove_dir_all(&cache_dir);
+}
+
+#[test]
+fn disable_default_plugin() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+      "plugin.svelte": false,
+    }))
+    .unwrap();
+    let result = handler.resolve_config(config, Default::default()).await;
+    assert!(result.diagnostics.is_empty());
+    assert!(!result.config.plugins.is_enabled("svelte"));
+    let extensions = result.file_matching.file_extensions;
+    assert!(extensions.iter().any(|ext| ext == "ts"));
+    assert!(!extensions.iter().any(|ext| ext == "svelte"));
+  });
+}
+
+#[test]
+fn format_astro_file() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    let result = handler
+      .resolve_config(ConfigKeyMap::new(), Default::default())
+      .await;
+    let extensions = result.file_matching.file_extensions;
+    assert!(!extensions.iter().any(|ext| ext == "astro"));
+
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+      "plugin.astro": true,
+    }))
+    .unwrap();
+    let result = handler.resolve_config(config, Default::default()).await;
+    assert!(result.diagnostics.is_empty());
+    let extensions = &result.file_matching.file_extensions;
+    assert!(extensions.iter().any(|ext| ext == "astro"));
+    let formatted = handler
+      .format(
+        create_request(
+          "file.astro",
+          "---\nconst   name =   \"world\"\n---\n<div>{name}</div>\n",
+          Arc::new(result.config),
+        ),
+        |_| std::future::ready(Ok(None)).boxed_local(),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(
+      String::from_utf8(formatted).unwrap(),
+      "---\nconst name = \"world\";\n---\n\n<div>{name}</div>\n"
+    );
+  });
+}
+
+#[test]
+fn handle_invalid_plugin_option() {
+  let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+    "svelteSortOrder": 5,
+    "svelte.svelteStrictMode": "yes",
+  }))
+  .unwrap();
+  let result = resolve_config(config, Default::default());
+  let property_names = result
+    .diagnostics
+    .iter()
+    .map(|diagnostic| diagnostic.property_name.as_str())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    property_names,
+    vec!["svelteSortOrder", "svelte.svelteStrictMode"]
+  );
+}
+
+#[test]
+fn handle_option_of_disabled_plugin() {
+  let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+    "plugin.svelte": false,
+    "svelteStrictMode": true,
+  }))
+  .unwrap();
+  let result = resolve_config(config, Default::default());
+  assert_eq!(result.diagnostics.len(), 1);
+  assert_eq!(result.diagnostics[0].property_name, "svelteStrictMode");
+  assert_eq!(
+    result.diagnostics[0].message,
+    "Has no effect because the 'svelte' plugin is disabled. Enable it with \"plugin.svelte\": true"
+  );
+}
+
+#[test]
+fn format_with_cursor() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    let file_text = "const   x = 'é';   y;";
+    let result = handler
+      .format_with_cursor(
+        create_request("file.js", file_text, Arc::new(Default::default())),
+        file_text.find('y').unwrap(),
+      )
+      .await
+      .unwrap()
+      .unwrap();
+    let formatted_text = String::from_utf8(result.text).unwrap();
+    assert_eq!(formatted_text, "const x = \"é\";\ny;\n");
+    assert_eq!(result.cursor_offset, formatted_text.find('y').unwrap());
+  });
+}
+
+#[test]
+fn format_batch_keeps_errors_separate() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let mut formatter = PrettierFormatter::new().unwrap();
+    let config = Arc::new(PrettierConfig::default());
+    let results = format_batch(
+      &mut formatter,
+      &config,
+      &["const   a = 1", "const v =", "const b = 2;\n"],
+    )
+    .await;
+    assert_eq!(results.len(), 3);
+    let mut results = results.into_iter();
+    assert_eq!(
+      results.next().unwrap().unwrap(),
+      Some("const a = 1;\n".to_string().into_bytes())
+    );
+    let err = results.next().unwrap().err().unwrap();
+    let expected = "SyntaxError: Unexpected token (1:10)";
+    assert_eq!(&err.to_string()[..expected.len()], expected);
+    assert_eq!(results.next().unwrap().unwrap(), None);
+  });
+}
+
+#[test]
+fn format_batch_warns_for_slow_files() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let slow_files = Arc::new(SlowFiles::default());
+    let mut formatter = PrettierFormatter::with_slow_files(slow_files.clone()).unwrap();
+    let mut config_map = ConfigKeyMap::new();
+    config_map.insert("slowFileWarningMs".to_string(), 0.into());
+    let config = Arc::new(resolve_config(config_map, Default::default()).config);
+    let results = format_batch(
+      &mut formatter,
+      &config,
+      &["const   a = 1", "const b = 2;\n"],
+    )
+    .await;
+    assert_eq!(results.len(), 2);
+    // every file takes at least 0ms, so each one in the batch is reported
+    assert!(slow_files.is_reported(Path::new("file0.js")));
+    assert!(slow_files.is_reported(Path::new("file1.js")));
+  });
+}
+
+fn create_request(
+  file_path: impl Into<PathBuf>,
+  file_text: &str,
+  config: Arc<PrettierConfig>,
+) -> FormatRequest<PrettierConfig> {
+  FormatRequest {
+    config_id: FormatConfigId::from_raw(0),
+    file_path: file_path.into(),
+    file_bytes: file_text.to_string().into_bytes(),
+    config,
+    range: None,
+    token: Arc::new(NullCancellationToken),
+  }
+}
+
+/// Formats the files in one batch and gets the results in the order of the files.
+async fn format_batch(
+  formatter: &mut PrettierFormatter,
+  config: &Arc<PrettierConfig>,
+  file_texts: &[&str],
+) -> Vec<Result<Option<Vec<u8>>, Error>> {
+  let requests = file_texts
+    .iter()
+    .enumerate()
+    .map(|(index, file_text)| {
+      create_request(format!("file{}.js", index), file_text, config.clone())
+    })
+    .collect();
+  let results = Rc::new(RefCell::new(Vec::new()));
+  formatter
+    .format_texts(requests, {
+      let results = results.clone();
+      Box::new(move |result: BatchResult| results.borrow_mut().push(result))
+    })
+    .await;
+  let mut results = results.take();
+  results.sort_by_key(|result| result.index);
+  results.into_iter().map(|result| result.result).collect()
+}
+
+#[test]
+fn rust_api_format_text() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let config = PrettierConfigBuilder::new()
+      .option("singleQuote", true)
+      .extension_option("js", "semi", false)
+      .build()
+      .unwrap();
+    let prettier = Prettier::default();
+    let result = prettier
+      .format_text("file.ts", "const   v = \"\"", &config)
+      .await
+      .unwrap();
+    assert_eq!(result.as_deref(), Some("const v = '';\n"));
+    let result = format_text("file.js", "const   v = \"\"", &config)
+      .await
+      .unwrap();
+    assert_eq!(result.as_deref(), Some("const v = ''\n"));
+    let result = prettier
+      .format_text("file.ts", "const v = '';\n", &config)
+      .await
+      .unwrap();
+    assert_eq!(result, None);
+
+    let err = PrettierConfigBuilder::new()
+      .option("slowFileWarningMs", "invalid")
+      .build()
+      .err()
+      .unwrap();
+    assert!(err.to_string().contains("slowFileWarningMs"));
+  });
+}
+
+#[test]
+fn cli_format_stdin() {
+  use std::io::Write;
+  use std::process::Command;
+  use std::process::Stdio;
+
+  let mut child = Command::new(env!("CARGO_BIN_EXE_dprint-plugin-prettier"))
+    .args(["--stdin", "--stdin-filepath", "file.ts"])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child
+    .stdin
+    .take()
+    .unwrap()
+    .write_all(b"const   v =  5")
+    .unwrap();
+  let output = child.wait_with_output().unwrap();
+  assert!(output.status.success());
+  assert_eq!(String::from_utf8(output.stdout).unwrap(), "const v = 5;\n");
+}
+
+/// Formats every corpus file for each supported extension, checking that the
+/// output is stable and that no state leaks between formats in an isolate.
+#[test]
+fn corpus_stability() {
+  let runtime = create_tokio_runtime();
+
+  runtime.block_on(async move {
+    let handler = PrettierPluginHandler::default();
+    let config: ConfigKeyMap = serde_json::from_value(serde_json::json!({
+      "plugin.astro": true,
+    }))
+    .unwrap();
+    let config_result = handler.resolve_config(config, Default::default()).await;
+    ensure_no_diagnostics(&config_result.diagnostics);
+    let config = Arc::new(config_result.config);
+
+    let corpus_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut fixtures = HashMap::new();
+    for entry in std::fs::read_dir(&corpus_dir).unwrap() {
+      let path = entry.unwrap().path();
+      let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+      let name = file_name.split('.').next().unwrap().to_string();
+      fixtures.insert(name, std::fs::read_to_string(&path).unwrap());
+    }
+
+    // format each fixture with every extension that uses its parser
+    let mut cases = Vec::new();
+    let mut uncovered_extensions = Vec::new();
+    let extension_parsers = get_extension_parsers();
+    for extension in &config_result.file_matching.file_extensions {
+      let mut names = extension_parsers
+        .get(extension)
+        .cloned()
+        .unwrap_or_default();
+      names.push(extension.clone());
+      match names.iter().find(|name| fixtures.contains_key(*name)) {
+        Some(name) => cases.push((format!("{}.{}", name, extension), &fixtures[name])),
+        None => uncovered_extensions.push(extension.clone()),
+      }
+    }
+    if !uncovered_extensions.is_empty() {
+      eprintln!(
+        "No corpus file for extensions: {}",
+        uncovered_extensions.join(", ")
+      );
+    }
+
+    let mut failures = Vec::new();
+    let mut first_outputs = HashMap::new();
+    let mut formatter = PrettierFormatter::new().unwrap();
+    for (file_name, file_text) in &cases {
+      let formatted = match format_corpus_file(&mut formatter, &config, file_name, file_text).await
+      {
+        Ok(formatted) => formatted,
+        Err(err) => {
+          failures.push(format!("{}: failed formatting: {:#}", file_name, err));
+          continue;
+        }
+      };
+      match format_corpus_file(&mut formatter, &config, file_name, &formatted).await {
+        Ok(reformatted) if reformatted != formatted => {
+          failures.push(format!("{}: formatting is not idempotent", file_name))
+        }
+        Ok(_) => {}
+        Err(err) => failures.push(format!(
+          "{}: failed formatting the formatted output: {:#}",
+          file_name, err
+        )),
+      }
+      first_outputs.insert(file_name, formatted);
+    }
+
+    drop(formatter);
+
+    // a fresh isolate formatting in reverse order should produce the same output
+    let mut formatter = PrettierFormatter::new().unwrap();
+    for (file_name, file_text) in cases.iter().rev() {
+      let Some(expected) = first_outputs.get(file_name) else {
+        continue;
+      };
+      match format_corpus_file(&mut formatter, &config, file_name, file_text).await {
+        Ok(formatted) if &formatted != expected => {
+          failures.push(format!("{}: output differs between isolates", file_name))
+        }
+        Ok(_) => {}
+        Err(err) => failures.push(format!(
+          "{}: failed formatting in second isolate: {:#}",
+          file_name, err
+        )),
+      }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+  });
+}
+
+async fn format_corpus_file(
+  formatter: &mut PrettierFormatter,
+  config: &Arc<PrettierConfig>,
+  file_name: &str,
+  file_text: &str,
+) -> Result<String, Error> {
+  let result = formatter
+    .format_text(create_request(file_name, file_text, config.clone()))
+    .await?;
+  Ok(match result {
+    Some(bytes) => String::from_utf8(bytes)?,
+    None => file_text.to_string(),
+  })
+}
@@ -0,0 +1,24 @@
+#!/usr/bin/env -S deno run -A
+
+// Creates a directory with a checksummed JS bundle that can be used to build
+// the plugin without npm by setting DPRINT_PRETTIER_PREBUILT_BUNDLE_DIR.
+//
+// Usage: ./scripts/create_prebuilt_bundle.ts <output-dir>
+
+import $ from "dax";
+
+const rootDir = $.path(import.meta.dirname!).parentOrThrow();
+const outputDir = $.path(Deno.args[0] ?? rootDir.join("js/prebuilt").toString());
+const distDir = rootDir.join("js/node/dist");
+
+// building the plugin runs the JS build and writes the license report beside the bundle
+await $`cargo build -p dprint-plugin-prettier`.cwd(rootDir);
+
+outputDir.mkdirSync({ recursive: true });
+distDir.join("main.js").copyFileSync(outputDir.join("main.js"));
+distDir.join("THIRD_PARTY_LICENSES.txt").copyFileSync(outputDir.join("THIRD_PARTY_LICENSES.txt"));
+const bundleBytes = outputDir.join("main.js").readBytesSync();
+const hash = await crypto.subtle.digest("SHA-256", bundleBytes);
+const checksum = Array.from(new Uint8Array(hash)).map(b => b.toString(16).padStart(2, "0")).join("");
+outputDir.join("main.js.sha256").writeTextSync(`${checksum}  main.js\n`);
+$.log(`Created prebuilt bundle in ${outputDir} (${checksum})`);